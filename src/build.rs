@@ -0,0 +1,662 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, hash_map::Entry},
+    fmt,
+};
+
+use crate::{
+    Alphabet, Direction, Probabilities, State, Symbol, Transitions,
+    error::BuildError,
+    intern::Interner,
+    parse::{Description, Metadata},
+};
+
+pub(crate) fn is_state_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+fn symbol_name(name: &str) -> Result<(), BuildError> {
+    if name.is_empty() {
+        return Err(BuildError::EmptySymbol);
+    }
+    Ok(())
+}
+
+/// Parses a probability written as a decimal number or a fraction, like
+/// `0.25` or `1/4`.
+pub(crate) fn parse_probability(text: &str) -> Result<f64, BuildError> {
+    let invalid = || BuildError::InvalidProbability(text.to_owned());
+    if !text
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | '/'))
+    {
+        return Err(invalid());
+    }
+    let probability = match text.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator = numerator.parse::<f64>().map_err(|_| invalid())?;
+            let denominator = denominator.parse::<f64>().map_err(|_| invalid())?;
+            numerator / denominator
+        }
+        None => text.parse().map_err(|_| invalid())?,
+    };
+    probability_range(probability).map_err(|_| invalid())
+}
+
+fn probability_range(probability: f64) -> Result<f64, BuildError> {
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(BuildError::ProbabilityRange(probability));
+    }
+    Ok(probability)
+}
+
+/// The probabilities of the choices for the same state and symbols, where
+/// the choices without one share what the others leave, or the sum of the
+/// given ones if they don't add up to 1.
+pub(crate) fn share(given: &[Option<f64>]) -> Result<Box<[f64]>, f64> {
+    const EPSILON: f64 = 1e-9;
+    let sum = given.iter().flatten().sum::<f64>();
+    let missing = given.iter().filter(|p| p.is_none()).count();
+    if sum > 1.0 + EPSILON || (missing == 0 && sum < 1.0 - EPSILON) {
+        return Err(round(sum));
+    }
+    let rest = (1.0 - sum).max(0.0) / missing.max(1) as f64;
+    Ok(given.iter().map(|p| p.unwrap_or(rest)).collect())
+}
+
+/// Rounds away the error that adding up probabilities accumulates, which
+/// is far below what a description would write.
+fn round(probability: f64) -> f64 {
+    (probability * 1e12).round() / 1e12
+}
+
+fn names(symbols: &Interner, reads: &[Symbol]) -> Vec<String> {
+    reads.iter().map(|&s| symbols.name(s).to_owned()).collect()
+}
+
+/// The probabilities given to the choices for the same state and symbols,
+/// if any.
+pub(crate) type Given = Vec<Option<f64>>;
+
+/// Builds a [`Description`] piece by piece, with the same validation as
+/// [`parse`](crate::parse) and the same error messages.
+#[derive(Debug)]
+pub struct Builder {
+    pub(crate) metadata: Metadata,
+    pub(crate) tapes: usize,
+    pub(crate) states: Interner,
+    pub(crate) symbols: Interner,
+    pub(crate) tape_alphabet: Option<Alphabet>,
+    pub(crate) alphabet: Alphabet,
+    pub(crate) blank: Option<Symbol>,
+    pub(crate) accepting: HashSet<State>,
+    pub(crate) rejecting: HashSet<State>,
+    pub(crate) init_state: Option<State>,
+    pub(crate) transitions: Transitions,
+    pub(crate) nondeterministic: bool,
+    pub(crate) probabilistic: bool,
+    pub(crate) probabilities: HashMap<(State, Box<[Symbol]>), Given>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            metadata: Metadata::default(),
+            tapes: 1,
+            states: Interner::default(),
+            symbols: Interner::default(),
+            tape_alphabet: None,
+            alphabet: Alphabet::new(),
+            blank: None,
+            accepting: HashSet::new(),
+            rejecting: HashSet::new(),
+            init_state: None,
+            transitions: Transitions::new(),
+            nondeterministic: false,
+            probabilistic: false,
+            probabilities: HashMap::new(),
+        }
+    }
+}
+
+impl Builder {
+    /// The most tapes a machine can have.
+    pub const MAX_TAPES: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of tapes, which is 1 unless set, up to
+    /// [`Builder::MAX_TAPES`]. The input is written on the first tape, the
+    /// others start blank.
+    pub fn tapes(&mut self, tapes: usize) -> Result<(), BuildError> {
+        if tapes == 0 {
+            return Err(BuildError::NoTapes);
+        }
+        if tapes > Self::MAX_TAPES {
+            return Err(BuildError::TooManyTapes(tapes));
+        }
+        self.tapes = tapes;
+        Ok(())
+    }
+
+    pub fn name(&mut self, name: &str) -> Result<(), BuildError> {
+        self.text("name", name)
+    }
+
+    pub fn description(&mut self, description: &str) -> Result<(), BuildError> {
+        self.text("description", description)
+    }
+
+    pub fn author(&mut self, author: &str) -> Result<(), BuildError> {
+        self.text("author", author)
+    }
+
+    pub(crate) fn text(&mut self, key: &str, value: &str) -> Result<(), BuildError> {
+        if value.is_empty() {
+            return Err(BuildError::EmptyText(key.to_owned()));
+        }
+        if value.contains(['\n', '\r']) {
+            return Err(BuildError::MultilineText(key.to_owned()));
+        }
+        let field = match key {
+            "name" => &mut self.metadata.name,
+            "description" => &mut self.metadata.description,
+            _ => &mut self.metadata.author,
+        };
+        *field = Some(value.to_owned());
+        Ok(())
+    }
+
+    /// Allows several transitions for the same state and symbol.
+    pub fn nondeterministic(&mut self, nondeterministic: bool) {
+        self.nondeterministic = nondeterministic;
+    }
+
+    /// Allows several transitions for the same state and symbols, each
+    /// followed with some probability, see
+    /// [`Builder::transition_with_probability`].
+    pub fn probabilistic(&mut self, probabilistic: bool) {
+        self.probabilistic = probabilistic;
+    }
+
+    /// Declares a symbol of the tape alphabet. Once one is declared, the
+    /// input symbols and the blank have to be declared with it first.
+    pub fn tape_symbol(&mut self, name: &str) -> Result<Symbol, BuildError> {
+        symbol_name(name)?;
+        let symbol = self.symbols.intern(name);
+        self.tape_alphabet.get_or_insert_default().insert(symbol);
+        Ok(symbol)
+    }
+
+    pub fn input_symbol(&mut self, name: &str) -> Result<Symbol, BuildError> {
+        let symbol = self.symbol(name, "input")?;
+        if self.blank == Some(symbol) {
+            return Err(BuildError::BlankInAlphabet(name.to_owned()));
+        }
+        self.alphabet.insert(symbol);
+        Ok(symbol)
+    }
+
+    pub fn blank(&mut self, name: &str) -> Result<Symbol, BuildError> {
+        let symbol = self.symbol(name, "blank")?;
+        if self.alphabet.contains(&symbol) {
+            return Err(BuildError::BlankInAlphabet(name.to_owned()));
+        }
+        self.blank = Some(symbol);
+        Ok(symbol)
+    }
+
+    fn symbol(&mut self, name: &str, what: &'static str) -> Result<Symbol, BuildError> {
+        symbol_name(name)?;
+        let Some(tape_alphabet) = &self.tape_alphabet else {
+            return Ok(self.symbols.intern(name));
+        };
+        self.symbols
+            .get(name)
+            .filter(|s| tape_alphabet.contains(s))
+            .ok_or_else(|| BuildError::NotInTapeAlphabet {
+                what,
+                name: name.to_owned(),
+            })
+    }
+
+    pub fn accept(&mut self, name: &str) -> Result<State, BuildError> {
+        let state = self.state(name, "accepting state")?;
+        self.halting(name, state, &self.rejecting)?;
+        self.accepting.insert(state);
+        Ok(state)
+    }
+
+    pub fn reject(&mut self, name: &str) -> Result<State, BuildError> {
+        let state = self.state(name, "rejecting state")?;
+        self.halting(name, state, &self.accepting)?;
+        self.rejecting.insert(state);
+        Ok(state)
+    }
+
+    fn halting(&self, name: &str, state: State, other: &HashSet<State>) -> Result<(), BuildError> {
+        if other.contains(&state) {
+            return Err(BuildError::AcceptingAndRejecting(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    pub fn start(&mut self, name: &str) -> Result<State, BuildError> {
+        let state = self.state(name, "initial state")?;
+        self.init_state = Some(state);
+        Ok(state)
+    }
+
+    fn state(&mut self, name: &str, what: &'static str) -> Result<State, BuildError> {
+        if name.is_empty() || !name.chars().all(is_state_char) {
+            return Err(BuildError::InvalidState {
+                what,
+                name: name.to_owned(),
+            });
+        }
+        Ok(self.states.intern(name))
+    }
+
+    /// Adds the transition from `state` reading the symbols in `read` to
+    /// `next`, writing the symbols in `write` and moving the heads in `dir`,
+    /// with one of each per tape. The symbols have to be declared already.
+    pub fn transition(
+        &mut self,
+        state: &str,
+        read: &[&str],
+        next: &str,
+        write: &[&str],
+        dir: &[Direction],
+    ) -> Result<(), BuildError> {
+        let count = |what, found| {
+            if found != self.tapes {
+                return Err(BuildError::TapeCount {
+                    what,
+                    expected: self.tapes,
+                    found,
+                });
+            }
+            Ok(())
+        };
+        let symbols = |what, counted, names: &[&str]| {
+            count(counted, names.len())?;
+            names
+                .iter()
+                .map(|&name| {
+                    self.symbols
+                        .get(name)
+                        .ok_or_else(|| BuildError::UnknownSymbol {
+                            what,
+                            name: name.to_owned(),
+                        })
+                })
+                .collect::<Result<Box<[_]>, _>>()
+        };
+        let read = symbols("head", "head symbol(s)", read)?;
+        let write = symbols("write", "write symbol(s)", write)?;
+        count("direction(s)", dir.len())?;
+        let state = self.state(state, "state")?;
+        let next = self.state(next, "next state")?;
+        let transition = (next, write, dir.into());
+        if self.probabilistic {
+            let probabilities = self.probabilities.entry((state, read.clone()));
+            probabilities.or_default().push(None);
+        }
+        match self.transitions.entry((state, read)) {
+            Entry::Occupied(mut e) if self.nondeterministic || self.probabilistic => {
+                e.get_mut().push(transition)
+            }
+            Entry::Occupied(e) => {
+                let (state, reads) = e.key();
+                return Err(BuildError::DuplicateTransition {
+                    state: self.states.name(*state).to_owned(),
+                    reads: names(&self.symbols, reads),
+                });
+            }
+            Entry::Vacant(e) => _ = e.insert(vec![transition]),
+        }
+        Ok(())
+    }
+
+    /// Adds a transition like [`Builder::transition`] that a probabilistic
+    /// machine follows with the given probability. The transitions for the
+    /// same state and symbols without a probability share what the others
+    /// leave.
+    pub fn transition_with_probability(
+        &mut self,
+        state: &str,
+        read: &[&str],
+        next: &str,
+        write: &[&str],
+        dir: &[Direction],
+        probability: f64,
+    ) -> Result<(), BuildError> {
+        if !self.probabilistic {
+            return Err(BuildError::UnexpectedProbability);
+        }
+        let probability = probability_range(probability)?;
+        self.transition(state, read, next, write, dir)?;
+        let read = read.iter().map(|&name| self.symbols.get(name));
+        let read = read.collect::<Option<Box<[_]>>>();
+        let state = self.states.get(state);
+        if let (Some(state), Some(read)) = (state, read)
+            && let Some(last) = self
+                .probabilities
+                .get_mut(&(state, read))
+                .and_then(|p| p.last_mut())
+        {
+            *last = Some(probability);
+        }
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<Description, BuildError> {
+        if self.nondeterministic && self.probabilistic {
+            return Err(BuildError::NondeterministicAndProbabilistic);
+        }
+        let mut probabilities = Probabilities::new();
+        for (key, given) in self.probabilities {
+            let shared = share(&given).map_err(|sum| BuildError::ProbabilitySum {
+                state: self.states.name(key.0).to_owned(),
+                reads: names(&self.symbols, &key.1),
+                sum,
+            })?;
+            probabilities.insert(key, shared);
+        }
+        let blank = self.blank.ok_or(BuildError::MissingBlank)?;
+        let init_state = self.init_state.ok_or(BuildError::MissingStart)?;
+        // The calls may have come in any order, so a symbol declared before
+        // the tape alphabet or the blank wasn't checked against them.
+        let mut alphabet = self.alphabet.iter().copied().collect::<Vec<_>>();
+        alphabet.sort_unstable();
+        if alphabet.contains(&blank) {
+            let name = self.symbols.name(blank).to_owned();
+            return Err(BuildError::BlankInAlphabet(name));
+        }
+        if let Some(tape_alphabet) = &self.tape_alphabet {
+            let symbols = alphabet.iter().map(|&s| ("input", s));
+            let outside = symbols
+                .chain([("blank", blank)])
+                .find(|(_, s)| !tape_alphabet.contains(s));
+            if let Some((what, s)) = outside {
+                let name = self.symbols.name(s).to_owned();
+                return Err(BuildError::NotInTapeAlphabet { what, name });
+            }
+        }
+        Ok(Description {
+            metadata: self.metadata,
+            tapes: self.tapes,
+            states: self.states,
+            symbols: self.symbols,
+            alphabet: self.alphabet,
+            blank,
+            accepting: self.accepting,
+            rejecting: self.rejecting,
+            init_state,
+            transitions: self.transitions,
+            nondeterministic: self.nondeterministic,
+            probabilistic: self.probabilistic,
+            probabilities,
+        })
+    }
+}
+
+/// Quotes a symbol if it would otherwise be read as something else.
+fn quote(name: &str) -> Cow<'_, str> {
+    let special = name == "*"
+        || name.starts_with(['#', '$', '{', '%'])
+        || name.starts_with("!{")
+        || name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '\'' | '\\'));
+    if !special {
+        return Cow::Borrowed(name);
+    }
+    let mut quoted = String::from('\'');
+    for c in name.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            '\\' | '\'' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// Writes the description in the keyed format, which reads back as the same
+/// machine.
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = |s: Symbol| quote(self.symbols.name(s));
+        let state = |s: State| self.states.name(s);
+        let list = |f: &mut fmt::Formatter<'_>, key: &str, items: Vec<Cow<'_, str>>| {
+            writeln!(
+                f,
+                "{key}:{}",
+                items.iter().map(|i| format!(" {i}")).collect::<String>()
+            )
+        };
+        writeln!(f, "version: 2")?;
+        if self.tapes > 1 {
+            writeln!(f, "tapes: {}", self.tapes)?;
+        }
+        for (key, value) in [
+            ("name", &self.metadata.name),
+            ("description", &self.metadata.description),
+            ("author", &self.metadata.author),
+        ] {
+            if let Some(value) = value {
+                writeln!(f, "{key}: {value}")?;
+            }
+        }
+        list(
+            f,
+            "tape-alphabet",
+            self.symbols.iter().map(|(_, n)| quote(n)).collect(),
+        )?;
+        let mut alphabet = self.alphabet.iter().copied().collect::<Vec<_>>();
+        alphabet.sort_unstable();
+        list(f, "alphabet", alphabet.into_iter().map(symbol).collect())?;
+        writeln!(f, "blank: {}", symbol(self.blank))?;
+        writeln!(f, "start: {}", state(self.init_state))?;
+        for (key, states) in [("accept", &self.accepting), ("reject", &self.rejecting)] {
+            if !states.is_empty() {
+                let mut states = states.iter().copied().collect::<Vec<_>>();
+                states.sort_unstable();
+                list(
+                    f,
+                    key,
+                    states.into_iter().map(|s| state(s).into()).collect(),
+                )?;
+            }
+        }
+        if self.nondeterministic {
+            writeln!(f, "nondeterministic: true")?;
+        }
+        if self.probabilistic {
+            writeln!(f, "probabilistic: true")?;
+        }
+        let mut keys = self.transitions.keys().cloned().collect::<Vec<_>>();
+        keys.sort_unstable();
+        let symbols = |symbols: &[Symbol]| {
+            let names = symbols.iter().map(|&s| symbol(s).into_owned());
+            names.collect::<Vec<_>>().join(" ")
+        };
+        for key in keys {
+            let probabilities = self.probabilities.get(&key);
+            for (i, (next, write, dir)) in self.transitions[&key].iter().enumerate() {
+                let dir = dir.iter().map(Direction::to_string);
+                write!(
+                    f,
+                    "{} {} {} {} {}",
+                    state(key.0),
+                    symbols(&key.1),
+                    state(*next),
+                    symbols(write),
+                    dir.collect::<Vec<_>>().join(" ")
+                )?;
+                match probabilities {
+                    Some(probabilities) => writeln!(f, " {}", round(probabilities[i]))?,
+                    None => writeln!(f)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> Builder {
+        let mut builder = Builder::new();
+        builder.input_symbol("a").unwrap();
+        builder.blank("_").unwrap();
+        builder.start("q").unwrap();
+        builder
+    }
+
+    #[test]
+    fn blank_before_input_symbol() {
+        let mut builder = Builder::new();
+        builder.blank("_").unwrap();
+        assert_eq!(
+            builder.input_symbol("_"),
+            Err(BuildError::BlankInAlphabet("_".to_owned()))
+        );
+        builder.start("q").unwrap();
+        builder.alphabet.insert(builder.symbols.get("_").unwrap());
+        assert_eq!(
+            builder.build().unwrap_err(),
+            BuildError::BlankInAlphabet("_".to_owned())
+        );
+    }
+
+    #[test]
+    fn input_symbol_before_tape_alphabet() {
+        let mut builder = Builder::new();
+        builder.input_symbol("a").unwrap();
+        builder.tape_symbol("_").unwrap();
+        builder.blank("_").unwrap();
+        builder.start("q").unwrap();
+        assert_eq!(
+            builder.build().unwrap_err(),
+            BuildError::NotInTapeAlphabet {
+                what: "input",
+                name: "a".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn tape_count() {
+        let mut builder = Builder::new();
+        assert_eq!(builder.tapes(0), Err(BuildError::NoTapes));
+        let too_many = Builder::MAX_TAPES + 1;
+        assert_eq!(
+            builder.tapes(too_many),
+            Err(BuildError::TooManyTapes(too_many))
+        );
+        builder.tapes(Builder::MAX_TAPES).unwrap();
+    }
+
+    #[test]
+    fn duplicate_transition() {
+        let mut builder = builder();
+        let right = [Direction::Right];
+        builder
+            .transition("q", &["a"], "q", &["a"], &right)
+            .unwrap();
+        assert_eq!(
+            builder.transition("q", &["a"], "r", &["_"], &right),
+            Err(BuildError::DuplicateTransition {
+                state: "q".to_owned(),
+                reads: vec!["a".to_owned()],
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_symbol() {
+        let mut builder = builder();
+        assert_eq!(
+            builder.transition("q", &["a"], "q", &["b"], &[Direction::Right]),
+            Err(BuildError::UnknownSymbol {
+                what: "write",
+                name: "b".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn bad_probability() {
+        assert_eq!(
+            parse_probability("3/2"),
+            Err(BuildError::InvalidProbability("3/2".to_owned()))
+        );
+        let mut builder = builder();
+        builder.probabilistic(true);
+        let none = [Direction::None];
+        assert_eq!(
+            builder.transition_with_probability("q", &["a"], "q", &["a"], &none, 1.5),
+            Err(BuildError::ProbabilityRange(1.5))
+        );
+        builder
+            .transition_with_probability("q", &["a"], "q", &["a"], &none, 0.5)
+            .unwrap();
+        builder
+            .transition_with_probability("q", &["a"], "q", &["_"], &none, 0.25)
+            .unwrap();
+        assert!(matches!(
+            builder.build(),
+            Err(BuildError::ProbabilitySum { sum: 0.75, .. })
+        ));
+    }
+
+    /// Parses the description that `description` displays as, which has to
+    /// display the same way again.
+    fn round_trip(description: &Description) -> Description {
+        let text = description.to_string();
+        let parsed = crate::parse("round trip", &text).unwrap();
+        assert_eq!(parsed.to_string(), text);
+        parsed
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut builder = builder();
+        builder.name("Machine #1").unwrap();
+        builder.author("someone # else").unwrap();
+        let parsed = round_trip(&builder.build().unwrap());
+        assert_eq!(parsed.metadata.name.as_deref(), Some("Machine #1"));
+        assert_eq!(parsed.metadata.author.as_deref(), Some("someone # else"));
+    }
+
+    #[test]
+    fn substituted_state_round_trip() {
+        let source = "version: 2
+alphabet: a '#' ' '
+blank: _
+start: q
+q $c:!{_} c_$c $c R
+c_$c _ q $c L
+";
+        let description = crate::parse("test", source).unwrap();
+        assert!(description.states.get("c_.23.").is_some());
+        assert!(description.states.get("c_.20.").is_some());
+        let parsed = round_trip(&description);
+        assert_eq!(parsed.states.len(), description.states.len());
+        assert_eq!(parsed.transitions.len(), description.transitions.len());
+    }
+}
@@ -0,0 +1,130 @@
+use turing_machine_sim::Strategy;
+
+pub const USAGE: &str = "usage: executable [options] <machine_description_path>
+
+options:
+    -n, --max-steps <steps>    stop machines that don't halt within <steps> steps
+    -c, --detect-cycles        stop machines that revisit an earlier configuration
+    -t, --detect-translated    stop machines that repeat themselves while drifting over blanks
+    -d, --max-depth <steps>    don't follow the branches of a nondeterministic machine past <steps> steps
+    -m, --max-configurations <count>
+                               stop searching after exploring <count> configurations
+    -s, --strategy <strategy>  search the branches of a nondeterministic machine breadth-first
+                               (the default), depth-first, with iterative-deepening or memoized
+    --dot <path>               write the computation trees of a nondeterministic machine as Graphviz DOT
+    --json <path>              write the computation trees of a nondeterministic machine as JSON
+    -r, --runs <count>         run a probabilistic machine <count> times on every input (1000 by default)
+    --seed <seed>              seed the choices of a probabilistic machine (0 by default)";
+
+#[derive(Debug)]
+pub struct Options {
+    pub path: String,
+    pub max_steps: Option<u64>,
+    pub detect_cycles: bool,
+    pub detect_translations: bool,
+    pub max_depth: Option<u64>,
+    pub max_configurations: Option<usize>,
+    pub strategy: Strategy,
+    pub dot: Option<String>,
+    pub json: Option<String>,
+    pub runs: u64,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            max_steps: None,
+            detect_cycles: false,
+            detect_translations: false,
+            max_depth: None,
+            max_configurations: None,
+            strategy: Strategy::default(),
+            dot: None,
+            json: None,
+            runs: 1000,
+            seed: 0,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for `{name}`"))
+            };
+            match arg.as_str() {
+                "-n" | "--max-steps" => {
+                    let steps = value(&arg)?;
+                    let steps = steps
+                        .parse()
+                        .map_err(|_| format!("invalid number of steps `{steps}`"))?;
+                    options.max_steps = Some(steps);
+                }
+                "-d" | "--max-depth" => {
+                    let steps = value(&arg)?;
+                    let steps = steps
+                        .parse()
+                        .map_err(|_| format!("invalid number of steps `{steps}`"))?;
+                    options.max_depth = Some(steps);
+                }
+                "-m" | "--max-configurations" => {
+                    let count = value(&arg)?;
+                    let count = count
+                        .parse()
+                        .map_err(|_| format!("invalid number of configurations `{count}`"))?;
+                    options.max_configurations = Some(count);
+                }
+                "-s" | "--strategy" => {
+                    let strategy = value(&arg)?;
+                    options.strategy = Strategy::try_from(strategy.as_str())
+                        .map_err(|e| format!("{e} `{strategy}`"))?;
+                }
+                "--dot" => options.dot = Some(value(&arg)?),
+                "--json" => options.json = Some(value(&arg)?),
+                "-r" | "--runs" => {
+                    let runs = value(&arg)?;
+                    options.runs = runs
+                        .parse()
+                        .ok()
+                        .filter(|&runs| runs > 0)
+                        .ok_or_else(|| format!("invalid number of runs `{runs}`"))?;
+                }
+                "--seed" => {
+                    let seed = value(&arg)?;
+                    options.seed = seed.parse().map_err(|_| format!("invalid seed `{seed}`"))?;
+                }
+                "-c" | "--detect-cycles" => options.detect_cycles = true,
+                "-t" | "--detect-translated" => options.detect_translations = true,
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+        options.path = path.ok_or("please specify a path for them machine description")?;
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|&arg| arg.to_owned()))
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(parse(&["-r", "10", "m.tm"]).unwrap().runs, 10);
+        assert_eq!(
+            parse(&["--runs", "0", "m.tm"]).unwrap_err(),
+            "invalid number of runs `0`"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    Configuration, State, Symbol,
+    tape::{Cell, Tape},
+};
+
+/// Brent's cycle detection over the configurations of a single run. Only
+/// one configuration is kept, saved again whenever the distance to it
+/// reaches the next power of two.
+#[derive(Debug)]
+pub struct Cycles {
+    saved: Configuration,
+    saved_at: u64,
+    power: u64,
+}
+
+impl Cycles {
+    /// Starts from `initial`, the configuration reached after `step` steps.
+    pub fn new(initial: &Configuration, step: u64) -> Self {
+        Self {
+            saved: initial.clone(),
+            saved_at: step,
+            power: 1,
+        }
+    }
+
+    /// Observes the configuration reached after `step` steps and returns
+    /// the period of the cycle once a configuration repeats.
+    pub fn observe(&mut self, step: u64, config: &Configuration) -> Option<u64> {
+        let distance = step - self.saved_at;
+        if *config == self.saved {
+            return Some(distance);
+        }
+        if distance == self.power {
+            self.saved = config.clone();
+            self.saved_at = step;
+            self.power *= 2;
+        }
+        None
+    }
+}
+
+/// Cells kept behind the head whenever it reaches a new edge of the tape.
+const WINDOW: usize = 64;
+/// Records kept per state on each edge.
+const HISTORY: usize = 8;
+
+/// The head reaching a cell beyond the visited region and the input.
+#[derive(Debug)]
+struct Record {
+    step: u64,
+    head: Cell,
+    low: Cell,
+    window: Vec<Symbol>,
+}
+
+/// One edge of the tape, seen through `sign` so that it always grows
+/// towards higher cells.
+#[derive(Debug)]
+struct Edge {
+    sign: Cell,
+    frontier: Cell,
+    records: HashMap<State, VecDeque<Record>>,
+}
+
+impl Edge {
+    fn new(sign: Cell, tape: &Tape) -> Self {
+        let span = tape.span();
+        Self {
+            sign,
+            frontier: if sign > 0 {
+                *span.end()
+            } else {
+                -*span.start()
+            },
+            records: HashMap::new(),
+        }
+    }
+
+    /// The run between a record and a later one in the same state only
+    /// reads cells between the lowest head position in between and the
+    /// earlier record, since everything past it is blank. If those cells
+    /// match the ones behind the later record, the run repeats from there
+    /// on fresh blank cells forever.
+    fn observe(&mut self, step: u64, config: &Configuration) -> Option<(u64, u64, Cell)> {
+        let (state, tape) = (&config.state, &config.tapes[0]);
+        let head = self.sign * tape.head();
+        for record in self.records.values_mut().flatten() {
+            record.low = record.low.min(head);
+        }
+        if head <= self.frontier {
+            return None;
+        }
+        self.frontier = head;
+        let behind = |i: Cell| tape.get(self.sign * (head - i));
+        let records = self.records.entry(*state).or_default();
+        for record in records.iter() {
+            let width = record.head - record.low;
+            if (width as usize) < WINDOW
+                && (0..=width).all(|i| record.window[i as usize] == behind(i))
+            {
+                let shift = self.sign * (head - record.head);
+                return Some((record.step, step - record.step, shift));
+            }
+        }
+        if records.len() == HISTORY {
+            records.pop_front();
+        }
+        records.push_back(Record {
+            step,
+            head,
+            low: head,
+            window: (0..WINDOW as Cell).map(behind).collect(),
+        });
+        None
+    }
+}
+
+/// Detection of translated cycles, where the machine keeps repeating the
+/// same steps while drifting over blank cells at either edge of the tape.
+/// Only single-tape machines are supported.
+#[derive(Debug)]
+pub struct Translations {
+    edges: [Edge; 2],
+}
+
+impl Translations {
+    pub fn new(initial: &Configuration) -> Self {
+        Self {
+            edges: [
+                Edge::new(1, &initial.tapes[0]),
+                Edge::new(-1, &initial.tapes[0]),
+            ],
+        }
+    }
+
+    /// Observes the configuration reached after `step` steps and returns
+    /// the step the repetition starts at, its period and how many cells
+    /// each period shifts it by.
+    pub fn observe(&mut self, step: u64, config: &Configuration) -> Option<(u64, u64, Cell)> {
+        let [right, left] = &mut self.edges;
+        let right = right.observe(step, config);
+        let left = left.observe(step, config);
+        right.or(left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Verdict, machine::tests::machine};
+
+    /// Walks right over the input and then goes around three states
+    /// without moving, entering the cycle after 3 steps.
+    const ROUNDS: &str =
+        "a\n_\nh\nq0\nq0 a q0 a R\nq0 _ p1 _ N\np1 _ p2 _ N\np2 _ p3 _ N\np3 _ p1 _ N\n";
+
+    #[test]
+    fn brent_finds_the_period() {
+        let mut machine = machine(ROUNDS, "aa");
+        let mut cycles = Cycles::new(machine.configuration(), 0);
+        let period = machine.steps().skip(1).take(64).find_map(|step| {
+            let step = step.unwrap();
+            cycles.observe(step.number, &step.configuration)
+        });
+        assert_eq!(period, Some(3));
+    }
+
+    #[test]
+    fn brent_reports_where_the_cycle_starts() {
+        let mut machine = machine(ROUNDS, "aa");
+        machine.detect_cycles(true);
+        let verdict = machine.execute(&mut ()).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict::Loop {
+                start: 3,
+                period: 3,
+                shift: 0
+            }
+        );
+    }
+
+    #[test]
+    fn drifting_right() {
+        let source = "a\n_\nh\nq\nq a q a R\nq _ r x R\nr _ q _ R\n%tape-alphabet a _ x\n";
+        let mut machine = machine(source, "aa");
+        machine.detect_translations(true);
+        let Verdict::Loop { period, shift, .. } = machine.execute(&mut ()).unwrap() else {
+            panic!("expected a translated cycle");
+        };
+        assert_eq!((period, shift), (2, 2));
+    }
+
+    #[test]
+    fn drifting_left() {
+        let source = "a\n_\nh\nq\nq a q a L\nq _ q _ L\n";
+        let mut machine = machine(source, "aa");
+        machine.detect_translations(true);
+        let Verdict::Loop { period, shift, .. } = machine.execute(&mut ()).unwrap() else {
+            panic!("expected a translated cycle");
+        };
+        assert_eq!((period, shift), (1, -1));
+    }
+
+    /// Zigzags between the edges, writing an `x` on the right and turning
+    /// the rightmost `a` into a `y` on every pass, and halts once no `a`
+    /// is left. Every pass reaches a new edge in the same state, but reads
+    /// back what the previous ones wrote.
+    #[test]
+    fn zigzag_halts() {
+        let source = "a\n_\nh\nf\n%tape-alphabet a _ x y\n\
+            f a f a R\nf x f x R\nf y f y R\nf _ b x L\n\
+            b x b x L\nb y b y L\nb a f y R\nb _ h _ N\n";
+        let mut machine = machine(source, &"a".repeat(12));
+        machine.detect_translations(true);
+        assert_eq!(machine.execute(&mut ()).unwrap(), Verdict::Accept);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub message: String,
+    pub notes: Vec<Diagnostic>,
+    source_line: String,
+}
+
+impl Diagnostic {
+    pub fn new(
+        file: &str,
+        line: usize,
+        column: usize,
+        token: &str,
+        source_line: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            file: file.to_owned(),
+            line,
+            column,
+            token: token.to_owned(),
+            message: message.into(),
+            notes: Vec::new(),
+            source_line: source_line.to_owned(),
+        }
+    }
+
+    pub fn with_note(mut self, note: Diagnostic) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, level: &str) -> fmt::Result {
+        let gutter = self.line.to_string().len();
+        writeln!(f, "{level}: {}", self.message)?;
+        writeln!(
+            f,
+            "{:gutter$}--> {}:{}:{}",
+            "", self.file, self.line, self.column
+        )?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{:gutter$} | {:pad$}{}",
+            "",
+            "",
+            "^".repeat(self.token.chars().count().max(1)),
+            pad = self.column - 1
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, "error")?;
+        for note in &self.notes {
+            writeln!(f)?;
+            note.render(f, "note")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, parse};
+
+    #[test]
+    fn caret_under_token() {
+        let error = Diagnostic::new("m.tm", 3, 6, "abc", "q0 a abc R", "unknown state `abc`");
+        assert_eq!(
+            error.to_string(),
+            "error: unknown state `abc`
+ --> m.tm:3:6
+  |
+3 | q0 a abc R
+  |      ^^^"
+        );
+    }
+
+    #[test]
+    fn empty_token_gets_one_caret() {
+        let error = Diagnostic::new("m.tm", 1, 3, "", "q0", "missing symbol");
+        assert!(error.to_string().ends_with("1 | q0\n  |   ^"));
+    }
+
+    #[test]
+    fn gutter_fits_line_number() {
+        let error = Diagnostic::new("m.tm", 120, 1, "q0", "q0 a", "unknown state `q0`");
+        assert_eq!(
+            error.to_string(),
+            "error: unknown state `q0`
+   --> m.tm:120:1
+    |
+120 | q0 a
+    | ^^"
+        );
+    }
+
+    #[test]
+    fn notes_follow_error() {
+        let note = Diagnostic::new("m.tm", 2, 1, "tapes", "tapes: 2", "first set here");
+        let error = Diagnostic::new("m.tm", 10, 1, "tapes", "tapes: 3", "`tapes` set twice")
+            .with_note(note);
+        assert_eq!(
+            error.to_string(),
+            "error: `tapes` set twice
+  --> m.tm:10:1
+   |
+10 | tapes: 3
+   | ^^^^^
+note: first set here
+ --> m.tm:2:1
+  |
+2 | tapes: 2
+  | ^^^^^"
+        );
+    }
+
+    #[test]
+    fn every_error_in_one_pass() {
+        let source = "version: 2
+alphabet: a
+blank: _
+start: q0
+accept: acc
+q0 a q0 a X
+q0 _ acc b N
+";
+        let Err(Error::Parse(diagnostics)) = parse("test", source) else {
+            panic!("expected parse errors");
+        };
+        let locations: Vec<_> = diagnostics.iter().map(|d| (d.line, d.column)).collect();
+        assert_eq!(locations, [(6, 11), (7, 10)], "{diagnostics:?}");
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+
+/// Errors that stop a run before it halts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// The head moved past the last cell a `Cell` can address.
+    EndOfTape,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EndOfTape => write!(f, "the head moved past the end of the tape"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Pieces of a machine that a [`Builder`](crate::Builder) refuses.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    EmptySymbol,
+    NoTapes,
+    /// More tapes than [`Builder::MAX_TAPES`](crate::Builder::MAX_TAPES).
+    TooManyTapes(usize),
+    /// The metadata `key` was given an empty value.
+    EmptyText(String),
+    /// The metadata `key` was given a value with a line break.
+    MultilineText(String),
+    /// A symbol was declared both as an input symbol and as the blank.
+    BlankInAlphabet(String),
+    /// An input symbol or the blank, as `what`, wasn't declared as a tape
+    /// symbol although a tape alphabet was.
+    NotInTapeAlphabet {
+        what: &'static str,
+        name: String,
+    },
+    /// A transition uses a symbol, as its `what` symbol, that wasn't
+    /// declared.
+    UnknownSymbol {
+        what: &'static str,
+        name: String,
+    },
+    /// A state name, of the `what` state, has characters other than
+    /// letters, digits, `_`, `-` and `.`.
+    InvalidState {
+        what: &'static str,
+        name: String,
+    },
+    AcceptingAndRejecting(String),
+    /// A transition has `found` symbols or directions, as `what`, instead of
+    /// one per tape.
+    TapeCount {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// A deterministic machine has a second transition for the same state
+    /// and symbols.
+    DuplicateTransition {
+        state: String,
+        reads: Vec<String>,
+    },
+    UnexpectedProbability,
+    /// A probability that isn't a number or a fraction between 0 and 1.
+    InvalidProbability(String),
+    ProbabilityRange(f64),
+    /// The probabilities of the transitions for the same state and symbols
+    /// don't add up to 1.
+    ProbabilitySum {
+        state: String,
+        reads: Vec<String>,
+        sum: f64,
+    },
+    NondeterministicAndProbabilistic,
+    MissingBlank,
+    MissingStart,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptySymbol => write!(f, "empty symbol"),
+            Self::NoTapes => write!(f, "a machine needs at least one tape"),
+            Self::TooManyTapes(tapes) => write!(
+                f,
+                "a machine can have at most {} tapes, not {tapes}",
+                crate::Builder::MAX_TAPES
+            ),
+            Self::EmptyText(key) => write!(f, "you should specify the {key}"),
+            Self::MultilineText(key) => write!(f, "the {key} must fit on one line"),
+            Self::BlankInAlphabet(name) => {
+                write!(f, "the blank symbol `{name}` can't be an input symbol")
+            }
+            Self::NotInTapeAlphabet { what, name } => {
+                write!(f, "{what} symbol `{name}` is not in the tape alphabet")
+            }
+            Self::UnknownSymbol { what, name } => {
+                write!(
+                    f,
+                    "invalid {what} symbol `{name}`, doesn't exist in the alphabet"
+                )
+            }
+            Self::InvalidState { what, name } => write!(f, "invalid {what} name `{name}`"),
+            Self::AcceptingAndRejecting(name) => {
+                write!(f, "state `{name}` is both accepting and rejecting")
+            }
+            Self::TapeCount {
+                what,
+                expected,
+                found,
+            } => write!(f, "expected {expected} {what}, one per tape, found {found}"),
+            Self::DuplicateTransition { state, reads } => write!(
+                f,
+                "transition from `{state}` on `{}` is already defined",
+                reads.join(", ")
+            ),
+            Self::UnexpectedProbability => write!(
+                f,
+                "only transitions of probabilistic machines have a probability"
+            ),
+            Self::InvalidProbability(text) => write!(
+                f,
+                "invalid probability `{text}`, expected a number between 0 and 1 like `0.25` or `1/4`"
+            ),
+            Self::ProbabilityRange(probability) => {
+                write!(f, "the probability {probability} isn't between 0 and 1")
+            }
+            Self::ProbabilitySum { state, reads, sum } => write!(
+                f,
+                "the probabilities of the transitions from `{state}` on `{}` add up to {sum}, not 1",
+                reads.join(", ")
+            ),
+            Self::NondeterministicAndProbabilistic => write!(
+                f,
+                "a machine can't be both nondeterministic and probabilistic"
+            ),
+            Self::MissingBlank => write!(f, "missing blank symbol"),
+            Self::MissingStart => write!(f, "missing initial state"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// The description is invalid, each diagnostic points at the offending
+    /// span of the source.
+    Parse(Vec<Diagnostic>),
+    /// A [`Builder`](crate::Builder) was given an invalid piece of a
+    /// machine.
+    Invalid(BuildError),
+    /// The input has no symbol of the input alphabet at `column`, counted
+    /// in characters from 1, where `text` starts.
+    InvalidInput {
+        column: usize,
+        text: String,
+    },
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Runtime(_) => 3,
+            Self::Parse(_) | Self::Invalid(_) => 64,
+            Self::InvalidInput { .. } => 65,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f, "\n")?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+            Self::InvalidInput { column, text } => {
+                write!(f, "invalid input symbol at column {column}: '{text}'")
+            }
+            Self::Invalid(e) => write!(f, "{e}"),
+            Self::Runtime(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Self::Runtime(e)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(e: BuildError) -> Self {
+        Self::Invalid(e)
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    error::RuntimeError,
+    machine::{Machine, Verdict},
+    observe::Observer,
+};
+
+/// How the runs of a probabilistic machine on the same input ended, see
+/// [`Machine::estimate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    pub runs: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Runs that halted in a state that is neither accepting nor rejecting.
+    pub undefined: u64,
+    /// Runs stopped by the step limit.
+    pub unfinished: u64,
+}
+
+impl Estimate {
+    /// The fraction of the runs that accepted.
+    pub fn probability(&self) -> f64 {
+        if self.runs == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.runs as f64
+    }
+
+    /// The Wilson score interval for the probability of acceptance, where
+    /// `z` is the quantile of the standard normal distribution for the
+    /// confidence level, like 1.96 for 95%. Unlike the usual normal
+    /// approximation it stays within `[0, 1]` and is still meaningful when
+    /// almost every run accepts or rejects.
+    pub fn interval(&self, z: f64) -> (f64, f64) {
+        if self.runs == 0 {
+            return (0.0, 1.0);
+        }
+        let (n, p) = (self.runs as f64, self.probability());
+        let z2 = z * z;
+        let denominator = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denominator;
+        let half = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+        // At the ends one bound is exactly 0 or 1, which rounding misses.
+        let low = if self.accepted == 0 {
+            0.0
+        } else {
+            (center - half).max(0.0)
+        };
+        let high = if self.accepted == self.runs {
+            1.0
+        } else {
+            (center + half).min(1.0)
+        };
+        (low, high)
+    }
+
+    /// `Accept` if more than half of the runs accepted, `Reject` otherwise.
+    pub fn verdict(&self) -> Verdict {
+        if 2 * self.accepted > self.runs {
+            Verdict::Accept
+        } else {
+            Verdict::Reject
+        }
+    }
+}
+
+impl Machine {
+    /// Runs a probabilistic machine `runs` times from the current
+    /// configuration and counts how the runs ended. Each run draws its
+    /// choices from where the previous one stopped, so the estimate only
+    /// depends on the seed, see [`Machine::seed`]. The machine is put back
+    /// in the current configuration afterwards.
+    pub fn estimate(
+        &mut self,
+        runs: u64,
+        observer: &mut impl Observer,
+    ) -> Result<Estimate, RuntimeError> {
+        let (initial, steps) = (self.config.clone(), self.steps);
+        let mut estimate = Estimate {
+            runs,
+            ..Estimate::default()
+        };
+        for _ in 0..runs {
+            self.config = initial.clone();
+            self.steps = steps;
+            match self.execute(observer)? {
+                Verdict::Accept => estimate.accepted += 1,
+                Verdict::Reject => estimate.rejected += 1,
+                Verdict::HaltedUndefined => estimate.undefined += 1,
+                Verdict::StepLimit(_)
+                | Verdict::SearchLimit
+                | Verdict::NeverAccepts
+                | Verdict::Loop { .. } => estimate.unfinished += 1,
+            }
+        }
+        self.config = initial;
+        self.steps = steps;
+        Ok(estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::tests::machine;
+
+    /// Accepts with probability 2/3 on the empty input.
+    const COIN: &str = "version: 2
+alphabet: a
+blank: _
+start: q
+accept: acc
+reject: rej
+probabilistic:
+q _ acc _ N 2/3
+q _ rej _ N
+";
+
+    fn estimate(seed: u64) -> Estimate {
+        let mut machine = machine(COIN, "");
+        machine.seed(seed);
+        machine.estimate(1000, &mut ()).unwrap()
+    }
+
+    #[test]
+    fn fixed_seed() {
+        let estimate = estimate(42);
+        assert_eq!(estimate, self::estimate(42));
+        assert_eq!(estimate.runs, 1000);
+        assert_eq!(estimate.accepted + estimate.rejected, 1000);
+        assert_eq!(estimate.accepted, 665);
+        assert_eq!(estimate.verdict(), Verdict::Accept);
+    }
+
+    fn counted(runs: u64, accepted: u64) -> Estimate {
+        Estimate {
+            runs,
+            accepted,
+            rejected: runs - accepted,
+            ..Estimate::default()
+        }
+    }
+
+    #[test]
+    fn interval_at_the_ends() {
+        let (low, high) = counted(100, 0).interval(1.96);
+        assert_eq!(low, 0.0);
+        assert!(0.0 < high && high < 0.05);
+        let (low, high) = counted(100, 100).interval(1.96);
+        assert!(0.95 < low && low < 1.0);
+        assert_eq!(high, 1.0);
+        assert_eq!(counted(0, 0).interval(1.96), (0.0, 1.0));
+    }
+}
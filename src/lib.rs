@@ -0,0 +1,88 @@
+//! Simulation of Turing machines loaded from their textual description.
+//!
+//! A description is loaded with [`parse`] into a [`Machine`], which can then
+//! be [`run`] on input words.
+
+mod build;
+mod detect;
+mod diagnostic;
+mod error;
+mod estimate;
+mod intern;
+mod machine;
+mod observe;
+mod parse;
+mod random;
+mod search;
+mod tape;
+mod tree;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+pub use build::Builder;
+pub use diagnostic::Diagnostic;
+pub use error::{BuildError, Error, RuntimeError};
+pub use estimate::Estimate;
+pub use intern::Interner;
+pub use machine::{Configuration, Machine, Step, Steps, Verdict};
+pub use observe::{Counter, Observer, Tracer};
+pub use parse::{Description, Metadata, parse};
+pub use random::Rng;
+pub use search::{Leaf, Limits, Search, Statistics, Strategy};
+pub use tape::{Cell, Tape};
+pub use tree::Tree;
+
+pub type State = usize;
+pub type Symbol = usize;
+pub type Alphabet = HashSet<Symbol>;
+/// The next state, and the symbol written and the head move on each tape.
+pub type Transition = (State, Box<[Symbol]>, Box<[Direction]>);
+/// The transitions from a state reading a symbol on each tape.
+pub type Transitions = HashMap<(State, Box<[Symbol]>), Vec<Transition>>;
+/// The probability of each of the transitions from a state reading a
+/// symbol on each tape, in the same order.
+pub type Probabilities = HashMap<(State, Box<[Symbol]>), Box<[f64]>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Right,
+    Left,
+    None,
+}
+
+impl TryFrom<&str> for Direction {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(match value {
+            "R" => Self::Right,
+            "L" => Self::Left,
+            "N" => Self::None,
+            _ => Err("invalid direction")?,
+        })
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Right => write!(f, "R"),
+            Self::Left => write!(f, "L"),
+            Self::None => write!(f, "N"),
+        }
+    }
+}
+
+/// Runs `machine` from its initial state on the input word `input`,
+/// reporting the events of the run to `observer`.
+pub fn run(
+    machine: &mut Machine,
+    input: &str,
+    observer: &mut impl Observer,
+) -> Result<Verdict, Error> {
+    machine.load(input)?;
+    Ok(machine.execute(observer)?)
+}
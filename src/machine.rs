@@ -0,0 +1,654 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    Alphabet, Direction, Probabilities, State, Symbol, Transition, Transitions,
+    detect::{Cycles, Translations},
+    error::{Error, RuntimeError},
+    intern::Interner,
+    observe::Observer,
+    parse::Description,
+    random::Rng,
+    tape::{Cell, Tape},
+};
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+    HaltedUndefined,
+    StepLimit(u64),
+    SearchLimit,
+    /// A memoized search went through every reachable configuration
+    /// without accepting, and some branch came back to a configuration it
+    /// had already been in, so it runs forever.
+    NeverAccepts,
+    Loop {
+        start: u64,
+        period: u64,
+        shift: Cell,
+    },
+}
+
+impl Verdict {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Accept => 0,
+            Self::Reject => 1,
+            Self::HaltedUndefined => 2,
+            Self::StepLimit(_) | Self::SearchLimit => 4,
+            Self::NeverAccepts | Self::Loop { .. } => 5,
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Accept => write!(f, "accept"),
+            Self::Reject => write!(f, "reject"),
+            Self::HaltedUndefined => write!(f, "halted without a transition"),
+            Self::StepLimit(steps) => write!(f, "did not halt within {steps} steps"),
+            Self::SearchLimit => write!(f, "no accepting branch within the search limits"),
+            Self::NeverAccepts => write!(f, "no accepting configuration is reachable"),
+            Self::Loop {
+                start,
+                period,
+                shift: 0,
+            } => write!(
+                f,
+                "loops forever: the configuration after step {start} repeats every {period} steps"
+            ),
+            Self::Loop {
+                start,
+                period,
+                shift,
+            } => write!(
+                f,
+                "loops forever: the steps after step {start} repeat every {period} steps, shifted {} cell(s) to the {}",
+                shift.unsigned_abs(),
+                if *shift > 0 { "right" } else { "left" }
+            ),
+        }
+    }
+}
+
+/// The state of a run: the current state and every tape with its head.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Configuration {
+    pub state: State,
+    pub tapes: Vec<Tape>,
+}
+
+impl Configuration {
+    /// The symbols under the heads.
+    pub fn reads(&self) -> Box<[Symbol]> {
+        self.tapes.iter().map(Tape::read).collect()
+    }
+
+    /// Every transition that applies, in the order they were declared.
+    pub(crate) fn choices<'a>(&self, transitions: &'a Transitions) -> &'a [Transition] {
+        transitions
+            .get(&(self.state, self.reads()))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn transition(&self, transitions: &Transitions) -> Option<Transition> {
+        self.choices(transitions).first().cloned()
+    }
+
+    /// Follows `transition` and returns the tapes that had to grow, with
+    /// the cell they grew by.
+    pub(crate) fn apply(
+        &mut self,
+        (next, write, dir): &Transition,
+    ) -> Result<Vec<(usize, Cell)>, RuntimeError> {
+        let mut grown = Vec::new();
+        for (i, tape) in self.tapes.iter_mut().enumerate() {
+            if tape.write(write[i]) {
+                grown.push((i, tape.head()));
+            }
+            tape.shift(dir[i])?;
+        }
+        self.state = *next;
+        Ok(grown)
+    }
+}
+
+/// A configuration reached during a run, after `number` steps and through
+/// `transition`, which is `None` for the initial configuration.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub number: u64,
+    pub transition: Option<Transition>,
+    pub configuration: Configuration,
+}
+
+/// Iterator over the steps of a run, see [`Machine::steps`].
+#[derive(Debug)]
+pub struct Steps<'a> {
+    machine: &'a mut Machine,
+    started: bool,
+    done: bool,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Result<Step, RuntimeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let transition = if self.started {
+            match self.machine.read(&mut ()) {
+                Ok(Some(transition)) => Some(transition),
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        } else {
+            self.started = true;
+            None
+        };
+        Some(Ok(Step {
+            number: self.machine.steps,
+            transition,
+            configuration: self.machine.config.clone(),
+        }))
+    }
+}
+
+/// A loaded machine together with the configuration of its current run.
+#[derive(Debug)]
+pub struct Machine {
+    states: Interner,
+    symbols: Interner,
+    separator: &'static str,
+    pub(crate) config: Configuration,
+    alphabet: Alphabet,
+    pub(crate) accepting: HashSet<State>,
+    rejecting: HashSet<State>,
+    init_state: State,
+    pub(crate) transitions: Transitions,
+    probabilities: Probabilities,
+    rng: Rng,
+    pub(crate) steps: u64,
+    step_limit: Option<u64>,
+    detect_cycles: bool,
+    detect_translations: bool,
+}
+
+impl Machine {
+    pub fn new(description: Description) -> Self {
+        let Description {
+            tapes,
+            states,
+            symbols,
+            alphabet,
+            blank,
+            accepting,
+            rejecting,
+            init_state,
+            transitions,
+            probabilities,
+            ..
+        } = description;
+        let separator = if symbols.iter().all(|(_, s)| s.chars().count() == 1) {
+            ""
+        } else {
+            " "
+        };
+        Self {
+            states,
+            symbols,
+            separator,
+            config: Configuration {
+                state: init_state,
+                tapes: vec![Tape::new(blank); tapes],
+            },
+            alphabet,
+            init_state,
+            accepting,
+            rejecting,
+            transitions,
+            probabilities,
+            rng: Rng::default(),
+            steps: 0,
+            step_limit: None,
+            detect_cycles: false,
+            detect_translations: false,
+        }
+    }
+
+    pub fn limit_steps(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    pub fn detect_cycles(&mut self, enabled: bool) {
+        self.detect_cycles = enabled;
+    }
+
+    pub fn detect_translations(&mut self, enabled: bool) {
+        self.detect_translations = enabled;
+    }
+
+    /// Restarts the generator that picks the transitions of a probabilistic
+    /// machine, which starts from the seed 0.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn is_probabilistic(&self) -> bool {
+        !self.probabilities.is_empty()
+    }
+
+    /// Writes the input word after the symbols already on the first tape.
+    /// Every whitespace-separated word is split into alphabet symbols,
+    /// preferring the longest symbol first, and backtracking when that
+    /// leaves a rest that can't be split. Nothing is written if some word
+    /// can't be split at all.
+    pub fn extend(&mut self, input: &str) -> Result<(), Error> {
+        let mut symbols = Vec::new();
+        let mut rest = input.trim_start();
+        while !rest.is_empty() {
+            let word = &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())];
+            let start = input.len() - rest.len();
+            let split = self.split(word).map_err(|at| Error::InvalidInput {
+                column: input[..start + at].chars().count() + 1,
+                text: word[at..].to_owned(),
+            })?;
+            symbols.extend(split);
+            rest = rest[word.len()..].trim_start();
+        }
+        let tape = &mut self.config.tapes[0];
+        symbols.into_iter().for_each(|s| tape.push(s));
+        Ok(())
+    }
+
+    /// Splits a word into input symbols, or gives the byte offset past the
+    /// longest prefix that some split reaches.
+    fn split(&self, word: &str) -> Result<Vec<Symbol>, usize> {
+        let matching = |i: usize| {
+            self.symbols
+                .iter()
+                .filter(move |&(s, name)| word[i..].starts_with(name) && self.alphabet.contains(&s))
+        };
+        // The longest symbol at every offset after which the rest splits.
+        let mut longest: Vec<Option<(Symbol, usize)>> = vec![None; word.len() + 1];
+        for i in (0..word.len()).rev().filter(|&i| word.is_char_boundary(i)) {
+            longest[i] = matching(i)
+                .map(|(s, name)| (s, name.len()))
+                .filter(|&(_, len)| i + len == word.len() || longest[i + len].is_some())
+                .max_by_key(|&(_, len)| len);
+        }
+        if word.is_empty() || longest[0].is_some() {
+            let mut symbols = Vec::new();
+            let mut i = 0;
+            while let Some((s, len)) = longest.get(i).copied().flatten() {
+                symbols.push(s);
+                i += len;
+            }
+            return Ok(symbols);
+        }
+        let mut reached = vec![false; word.len() + 1];
+        reached[0] = true;
+        for i in 0..word.len() {
+            if reached[i] && word.is_char_boundary(i) {
+                for (_, name) in matching(i) {
+                    reached[i + name.len()] = true;
+                }
+            }
+        }
+        Err(reached.iter().rposition(|&r| r).unwrap_or(0))
+    }
+
+    pub fn configuration(&self) -> &Configuration {
+        &self.config
+    }
+
+    /// The current configuration in describe notation, see
+    /// [`Machine::render`].
+    pub fn describe(&self) -> String {
+        self.render(&self.config)
+    }
+
+    /// A configuration in describe notation, with the state in parentheses
+    /// right before the symbol under the head. With several tapes every
+    /// tape gets a numbered line, with the cells aligned.
+    pub fn render(&self, config: &Configuration) -> String {
+        self.layout(&config.tapes, Some(config.state))
+    }
+
+    /// The cells of the tapes from the leftmost to the rightmost one of
+    /// any tape, with `state` before the cell under each head if given.
+    fn layout(&self, tapes: &[Tape], state: Option<State>) -> String {
+        let start = tapes.iter().map(|t| *t.span().start()).min().unwrap_or(0);
+        let end = tapes.iter().map(|t| *t.span().end()).max().unwrap_or(0);
+        let rows = tapes
+            .iter()
+            .map(|tape| {
+                let mut cells = Vec::new();
+                for i in start..=end {
+                    if i == 0 && start < 0 {
+                        cells.push("|".to_owned());
+                    }
+                    let s = self.symbols.name(tape.get(i));
+                    match state {
+                        Some(state) if i == tape.head() => {
+                            cells.push(format!("({}){s}", self.states.name(state)))
+                        }
+                        _ => cells.push(s.to_owned()),
+                    }
+                }
+                cells
+            })
+            .collect::<Vec<_>>();
+        if let [cells] = rows.as_slice() {
+            return cells.join(self.separator);
+        }
+        let widths = (0..rows[0].len())
+            .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let lines = rows.iter().enumerate().map(|(i, cells)| {
+            let cells = cells.iter().zip(&widths).map(|(c, &w)| format!("{c:w$}"));
+            let line = cells.collect::<Vec<_>>().join(" ");
+            format!("{}: {}", i + 1, line.trim_end())
+        });
+        lines.collect::<Vec<_>>().join("\n")
+    }
+
+    /// A transition from the state and the symbols it reads, in the format
+    /// of the transition lines of a description.
+    pub fn render_transition(
+        &self,
+        (state, reads): (State, &[Symbol]),
+        (next, write, dir): &Transition,
+    ) -> String {
+        let mut words = vec![self.states.name(state)];
+        words.extend(reads.iter().map(|&s| self.symbols.name(s)));
+        words.push(self.states.name(*next));
+        words.extend(write.iter().map(|&s| self.symbols.name(s)));
+        let dir = dir.iter().map(Direction::to_string).collect::<Vec<_>>();
+        words.extend(dir.iter().map(String::as_str));
+        words.join(" ")
+    }
+
+    /// Runs the machine from the current configuration until it halts or
+    /// one of the step limit and the detectors stops it, reporting the
+    /// events of the run to `observer`. A probabilistic machine can leave
+    /// a configuration differently the next time, so the detectors don't
+    /// apply to it.
+    pub fn execute(&mut self, observer: &mut impl Observer) -> Result<Verdict, RuntimeError> {
+        let deterministic = !self.is_probabilistic();
+        let mut cycles = (self.detect_cycles && deterministic)
+            .then(|| (self.config.clone(), Cycles::new(&self.config, self.steps)));
+        let detect_translations = self.detect_translations && deterministic;
+        let mut translations = (detect_translations && self.config.tapes.len() == 1)
+            .then(|| Translations::new(&self.config));
+        let first = self.steps;
+        observer.start(self);
+        loop {
+            if self.step_limit.is_some_and(|limit| self.steps >= limit)
+                && self.transition().is_some()
+            {
+                return Ok(Verdict::StepLimit(self.steps));
+            }
+            if self.read(observer)?.is_none() {
+                break;
+            }
+            if let Some((initial, cycles)) = &mut cycles
+                && let Some(period) = cycles.observe(self.steps, &self.config)
+            {
+                return Ok(Verdict::Loop {
+                    start: self.cycle_start(initial.clone(), first, period)?,
+                    period,
+                    shift: 0,
+                });
+            }
+            if let Some(translations) = &mut translations
+                && let Some((start, period, shift)) = translations.observe(self.steps, &self.config)
+            {
+                return Ok(Verdict::Loop {
+                    start,
+                    period,
+                    shift,
+                });
+            }
+        }
+        Ok(self.halted())
+    }
+
+    /// Steps through the run from the current configuration, starting with
+    /// the current configuration itself, until the machine halts or fails.
+    /// Unlike [`Machine::execute`] nothing is printed and neither the step
+    /// limit nor the detectors apply.
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps {
+            machine: self,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// The verdict of the run if the machine has halted.
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.transition().is_none().then(|| self.halted())
+    }
+
+    fn halted(&self) -> Verdict {
+        if self.accepting.contains(&self.config.state) {
+            Verdict::Accept
+        } else if self.rejecting.contains(&self.config.state) {
+            Verdict::Reject
+        } else {
+            Verdict::HaltedUndefined
+        }
+    }
+
+    /// Finds the first step whose configuration repeats `period` steps
+    /// later by replaying the run from `initial`, the configuration after
+    /// step `first`, with two configurations kept `period` steps apart.
+    fn cycle_start(
+        &self,
+        initial: Configuration,
+        first: u64,
+        period: u64,
+    ) -> Result<u64, RuntimeError> {
+        let advance = |config: &mut Configuration| match config.transition(&self.transitions) {
+            Some(transition) => config.apply(&transition).map(drop),
+            None => Ok(()),
+        };
+        let mut tortoise = initial.clone();
+        let mut hare = initial;
+        for _ in 0..period {
+            advance(&mut hare)?;
+        }
+        let mut start = first;
+        while tortoise != hare && start < self.steps {
+            advance(&mut tortoise)?;
+            advance(&mut hare)?;
+            start += 1;
+        }
+        Ok(start)
+    }
+
+    pub fn transition(&self) -> Option<Transition> {
+        self.config.transition(&self.transitions)
+    }
+
+    /// The transition to follow, drawn at random with the probabilities of
+    /// the choices for a probabilistic machine.
+    fn choose(&mut self) -> Option<Transition> {
+        let key = (self.config.state, self.config.reads());
+        let choices = self.transitions.get(&key)?;
+        let Some(probabilities) = self.probabilities.get(&key) else {
+            return choices.first().cloned();
+        };
+        let mut choices = choices.iter().zip(probabilities);
+        let mut draw = self.rng.next_f64();
+        if let Some((transition, _)) = choices.clone().find(|&(_, &probability)| {
+            draw -= probability;
+            draw < 0.0
+        }) {
+            return Some(transition.clone());
+        }
+        // Rounding can leave the draw just above the sum of the
+        // probabilities.
+        choices
+            .rfind(|&(_, &probability)| probability > 0.0)
+            .map(|(transition, _)| transition.clone())
+    }
+
+    /// Takes a single step and returns the transition it followed, or
+    /// `None` if the machine has halted.
+    pub fn read(
+        &mut self,
+        observer: &mut impl Observer,
+    ) -> Result<Option<Transition>, RuntimeError> {
+        let Some(transition) = self.choose() else {
+            observer.halt(self, self.halted());
+            return Ok(None);
+        };
+        let (state, reads) = (self.config.state, self.config.reads());
+        self.steps += 1;
+        for (tape, cell) in self.config.apply(&transition)? {
+            observer.grow(self, tape, cell);
+        }
+        observer.transition(self, (state, &reads), &transition);
+        Ok(Some(transition))
+    }
+
+    /// The contents of the tapes, laid out like in [`Machine::render`]:
+    /// the start of the input is marked, and several tapes get a numbered
+    /// line each with the cells aligned.
+    pub fn tape(&self) -> String {
+        self.layout(&self.config.tapes, None)
+    }
+
+    /// Resets the machine and writes the input word on the tape.
+    pub fn load(&mut self, input: &str) -> Result<(), Error> {
+        self.reset();
+        self.extend(input)
+    }
+
+    pub fn reset(&mut self) {
+        self.config.state = self.init_state;
+        self.steps = 0;
+        self.config.tapes.iter_mut().for_each(Tape::reset);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::parse;
+
+    /// Walks right over the input, then bounces between the last symbol
+    /// and the blank after it forever.
+    const BOUNCE: &str = "a\n_\nh\nq0\nq0 a q0 a R\nq0 _ q1 _ L\nq1 a q2 a R\nq2 _ q1 _ L\n";
+
+    /// A machine parsed from `source` with `input` loaded.
+    pub(crate) fn machine(source: &str, input: &str) -> Machine {
+        let mut machine = Machine::new(parse("test", source).unwrap());
+        machine.load(input).unwrap();
+        machine
+    }
+
+    #[test]
+    fn cycle_from_the_start() {
+        let mut machine = machine(BOUNCE, "aaa");
+        machine.detect_cycles(true);
+        let verdict = machine.execute(&mut ()).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict::Loop {
+                start: 4,
+                period: 2,
+                shift: 0
+            }
+        );
+    }
+
+    #[test]
+    fn cycle_after_stepping() {
+        let mut machine = machine(BOUNCE, "aaa");
+        assert_eq!(machine.steps().take(3).count(), 3);
+        machine.detect_cycles(true);
+        let verdict = machine.execute(&mut ()).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict::Loop {
+                start: 4,
+                period: 2,
+                shift: 0
+            }
+        );
+    }
+
+    #[test]
+    fn step_limit_already_passed() {
+        let mut machine = machine(BOUNCE, "aaa");
+        assert_eq!(machine.steps().take(8).count(), 8);
+        machine.limit_steps(Some(5));
+        assert_eq!(machine.execute(&mut ()).unwrap(), Verdict::StepLimit(7));
+    }
+
+    /// The input alphabet `a ab bc`, where the longest first symbol of
+    /// `abc` leaves a rest that can't be split.
+    const OVERLAP: &str = "a ab bc\n_\nh\nq0\n";
+
+    fn tape(machine: &Machine) -> Vec<&str> {
+        let tape = &machine.configuration().tapes[0];
+        tape.span()
+            .map(|i| machine.symbols.name(tape.get(i)))
+            .collect()
+    }
+
+    #[test]
+    fn split_backtracks() {
+        assert_eq!(tape(&machine(OVERLAP, "abc")), ["a", "bc"]);
+        assert_eq!(tape(&machine(OVERLAP, "aba ab")), ["ab", "a", "ab"]);
+    }
+
+    #[test]
+    fn split_reports_the_rest() {
+        let mut machine = Machine::new(parse("test", OVERLAP).unwrap());
+        let error = machine.load("a abxb").unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidInput { column: 5, ref text } if text == "xb"
+        ));
+        assert!(tape(&machine).iter().all(|&s| s == "_"));
+    }
+
+    #[test]
+    fn final_tapes_aligned() {
+        let source = "version: 2
+tapes: 2
+alphabet: a bc
+blank: _
+start: q
+accept: h
+q a _ q a x R N
+q bc * q bc * R N
+q _ * h _ * L L
+%tape-alphabet a bc _ x
+";
+        let mut machine = machine(source, "abc");
+        assert_eq!(machine.execute(&mut ()).unwrap(), Verdict::Accept);
+        assert_eq!(machine.tape(), "1: _ | a bc _\n2: _ | x _  _");
+    }
+
+    #[test]
+    fn final_tape_marks_the_input() {
+        let source = "a b\n_\nyes\nq0\nq0 a t a L\nt _ yes b R\n";
+        let mut machine = machine(source, "a");
+        assert_eq!(machine.execute(&mut ()).unwrap(), Verdict::Accept);
+        assert_eq!(machine.tape(), "b|a");
+    }
+}
@@ -1,228 +1,187 @@
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    error::Error,
-    fs::File,
-    io::{BufRead, BufReader},
-    process::ExitCode,
-};
+mod cli;
 
-type State = usize;
-type Symbol = char;
-type Alphabet = HashSet<char>;
-type Tape = VecDeque<char>;
+use std::{error::Error, io::BufRead, process::ExitCode};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Direction {
-    Right,
-    Left,
-    None,
-}
+use cli::{Options, USAGE};
+use turing_machine_sim::{
+    Error as RunError, Limits, Machine, Observer, Strategy, Tracer, Tree, Verdict, parse,
+};
 
-impl TryFrom<char> for Direction {
-    type Error = &'static str;
+const LOAD_FAILURE: u8 = 64;
 
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        Ok(match value {
-            'R' => Self::Right,
-            'L' => Self::Left,
-            'N' => Self::None,
-            _ => Err("invalid character for direction")?,
-        })
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            LOAD_FAILURE.into()
+        }
     }
 }
 
-#[derive(Debug)]
-struct Machine {
-    tape: Tape,
-    head: usize,
-    alphabet: Alphabet,
-    blank: Symbol,
-    accepting: HashSet<State>,
-    init_state: State,
-    state: State,
-    transitions: HashMap<(State, Symbol), (State, Symbol, Direction)>,
-}
-
-impl Machine {
-    fn new(
-        alphabet: Alphabet,
-        blank: Symbol,
-        accepting: HashSet<State>,
-        init_state: State,
-        transitions: HashMap<(State, Symbol), (State, Symbol, Direction)>,
-    ) -> Self {
-        Self {
-            tape: VecDeque::new(),
-            head: 0,
-            alphabet,
-            blank,
-            init_state,
-            state: init_state,
-            accepting,
-            transitions,
+fn run() -> Result<ExitCode, Box<dyn Error>> {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{USAGE}");
+            return Err(e.into());
         }
-    }
-
-    fn extend(&mut self, tape: &str) {
-        self.tape.extend(tape.chars().map(|s| {
-            if !self.alphabet.contains(&s) && s != self.blank {
-                panic!("invalid tape symbol: '{s}'");
-            } else {
-                s
-            }
-        }));
-    }
-
-    fn describe(&self) {
-        for (i, s) in self.tape.iter().enumerate() {
-            if i == self.head {
-                print!("({})", self.state);
+    };
+    let path = &options.path;
+
+    let source = std::fs::read_to_string(path)?;
+    let description = match parse(path, &source) {
+        Ok(description) => description,
+        Err(RunError::Parse(diagnostics)) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}\n");
             }
-            print!("{s}");
+            return Err(format!("{path}: {} parsing error(s)", diagnostics.len()).into());
         }
-        println!()
-    }
+        Err(e) => return Err(e.into()),
+    };
 
-    fn execute(&mut self) -> bool {
-        loop {
-            self.describe();
-            if !self.read() {
-                break;
-            }
+    let metadata = &description.metadata;
+    for (key, value) in [
+        ("name", &metadata.name),
+        ("author", &metadata.author),
+        ("description", &metadata.description),
+    ] {
+        if let Some(value) = value {
+            eprintln!("{key}: {value}");
         }
-        self.accepting.contains(&self.state)
     }
+    if options.detect_translations && description.tapes > 1 {
+        eprintln!("warning: translated cycles are only detected on single-tape machines");
+    }
+    let export = options.dot.is_some() || options.json.is_some();
+    if export && !description.nondeterministic {
+        eprintln!("warning: computation trees are only exported for nondeterministic machines");
+    }
+    let probabilistic = description.probabilistic;
+    if probabilistic && (options.detect_cycles || options.detect_translations) {
+        eprintln!("warning: loops aren't detected on probabilistic machines");
+    }
+    let nondeterministic = description.nondeterministic.then_some(options.strategy);
+    let limits = Limits {
+        depth: options.max_depth,
+        configurations: options.max_configurations,
+    };
+    let mut machine = Machine::new(description);
+    machine.limit_steps(options.max_steps);
+    machine.detect_cycles(options.detect_cycles);
+    machine.detect_translations(options.detect_translations);
 
-    fn read(&mut self) -> bool {
-        let Some((next, sym, dir)) = self.transitions.get(&(self.state, self.tape[self.head]))
-        else {
-            return false;
-        };
-        self.tape[self.head] = *sym;
-        self.state = *next;
-        self.head = match dir {
-            Direction::Right => {
-                if self.head >= usize::MAX - 1 {
-                    panic!("end of tape");
-                } else {
-                    if self.head + 1 >= self.tape.len() {
-                        self.tape.push_back(self.blank);
-                    }
-                    self.head + 1
-                }
+    let mut exit_code = 0;
+    let mut trees = Vec::new();
+    let mut stdin = std::io::stdin().lock();
+    let mut line = Vec::new();
+    for number in 0.. {
+        line.clear();
+        if stdin.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let result = match (std::str::from_utf8(line), nondeterministic) {
+            (Err(e), _) => Err(not_utf8(line, e.valid_up_to())),
+            (Ok(input), _) if probabilistic => {
+                estimate(&mut machine, input, options.runs, options.seed)
             }
-            Direction::Left => {
-                if self.head == 0 {
-                    self.tape.push_front(self.blank);
+            (Ok(input), Some(strategy)) if export => {
+                let mut tree = Tree::default();
+                let result = search(&mut machine, input, strategy, limits, &mut tree);
+                if !tree.is_empty() {
+                    trees.push(tree);
                 }
-                self.head.min(self.head.wrapping_sub(1))
+                result
             }
-            _ => self.head,
+            (Ok(input), Some(strategy)) => search(&mut machine, input, strategy, limits, &mut ()),
+            (Ok(input), None) => turing_machine_sim::run(&mut machine, input, &mut Tracer),
         };
-        true
+        match result {
+            Ok(verdict) => {
+                println!("{}", machine.tape());
+                println!("{verdict}");
+                if let Verdict::StepLimit(_) | Verdict::Loop { .. } = verdict {
+                    println!("stopped at {}", machine.describe());
+                }
+                exit_code = verdict.exit_code();
+            }
+            Err(e @ RunError::InvalidInput { .. }) => {
+                eprintln!("line {}: {e}, skipping it", number + 1);
+                exit_code = e.exit_code();
+            }
+            Err(e) => {
+                println!("{}", machine.tape());
+                println!("error: {e}");
+                println!("stopped at {}", machine.describe());
+                exit_code = e.exit_code();
+            }
+        }
     }
 
-    fn tape(&self) -> String {
-        self.tape.iter().collect::<String>()
+    if let Some(path) = &options.dot {
+        std::fs::write(path, trees.iter().map(Tree::dot).collect::<String>())?;
     }
-
-    fn reset(&mut self) {
-        self.state = self.init_state;
-        self.head = 0;
-        self.tape.clear();
+    if let Some(path) = &options.json {
+        let trees = trees.iter().map(Tree::json).collect::<Vec<_>>();
+        std::fs::write(path, format!("[\n{}\n]\n", trees.join(",\n")))?;
     }
-}
-
-fn main() -> Result<ExitCode, Box<dyn Error>> {
-    let Some(path) = std::env::args().nth(1) else {
-        println!("usage: executable <machine_description_path>");
-        return Err("please specify a path for them machine description".into());
-    };
-
-    let data = BufReader::new(File::open(path)?);
-    let mut lines = data.lines().filter(|l| {
-        l.as_ref()
-            .is_ok_and(|s| !s.starts_with('#') && !s.is_empty())
-            || l.is_err()
-    });
 
-    // TODO:
-    // - Report line number with parsing error.
-    let alphabet = lines
-        .next()
-        .ok_or("no alphabet")??
-        .split_whitespace()
-        .filter_map(|s| s.chars().next())
-        .collect::<HashSet<Symbol>>();
-    let blank = lines
-        .next()
-        .ok_or("no line for blank character")??
-        .split_whitespace()
-        .filter_map(|s| s.chars().next())
-        .next()
-        .ok_or("you should specify a blank character")?;
-    let accepting = lines
-        .next()
-        .ok_or("no line for accepting states")??
-        .split_whitespace()
-        .filter_map(|s| s.parse().ok())
-        .collect::<HashSet<State>>();
-    let init_state = lines
-        .next()
-        .ok_or("no line for initial state")??
-        .split_whitespace()
-        .flat_map(|s| s.parse::<State>().ok())
-        .next()
-        .ok_or("you should specify a initial state")?;
-    let transitions: HashMap<_, _> = lines
-        .map_while(Result::ok)
-        .filter(|s| !s.is_empty())
-        .map(|s| {
-            let mut iter = s.split_whitespace();
-            let state = iter
-                .next()
-                .ok_or("the current state was not specified")?
-                .parse::<State>()
-                .map_err(|_| "invalid state")?;
-            let head_sym = iter
-                .next()
-                .and_then(|s| s.chars().next())
-                .ok_or("the head symbol was not specified")?;
-            if !alphabet.contains(&head_sym) && head_sym != blank {
-                return Err("invalid head symbol, doesn't exist in the alphabet");
-            }
-            let next_state = iter
-                .next()
-                .ok_or("the next state was not specified")?
-                .parse::<State>()
-                .map_err(|_| "invalid next state")?;
-            let write_sym = iter
-                .next()
-                .and_then(|s| s.chars().next())
-                .ok_or("the write symbol was not specified")?;
-            if !alphabet.contains(&write_sym) && write_sym != blank {
-                println!("{:?}", write_sym != blank);
-                return Err("invalid write symbol, doesn't exist in the alphabet");
-            }
-            let dir = iter
-                .next()
-                .and_then(|s| s.chars().next())
-                .ok_or("the direction was not specified")
-                .and_then(Direction::try_from)?;
-            Ok::<_, &str>(((state, head_sym), (next_state, write_sym, dir)))
-        })
-        .collect::<Result<_, _>>()?;
+    Ok(exit_code.into())
+}
 
-    let mut machine = Machine::new(alphabet, blank, accepting, init_state, transitions);
+/// The error for an input line that isn't UTF-8 from byte `valid` on,
+/// pointing at the rest of the word there.
+fn not_utf8(line: &[u8], valid: usize) -> RunError {
+    let (prefix, rest) = line.split_at(valid);
+    let end = rest
+        .iter()
+        .position(u8::is_ascii_whitespace)
+        .unwrap_or(rest.len());
+    RunError::InvalidInput {
+        column: String::from_utf8_lossy(prefix).chars().count() + 1,
+        text: String::from_utf8_lossy(&rest[..end]).into_owned(),
+    }
+}
 
-    let mut exit_code = 0;
-    for line in std::io::stdin().lock().lines() {
-        let tape = line?;
-        machine.reset();
-        machine.extend(&tape);
-        exit_code = if machine.execute() { 0 } else { 1 };
-        println!("{}", machine.tape());
+/// Runs a probabilistic machine many times and prints how likely it is to
+/// accept, with a 95% confidence interval.
+fn estimate(machine: &mut Machine, input: &str, runs: u64, seed: u64) -> Result<Verdict, RunError> {
+    machine.load(input)?;
+    machine.seed(seed);
+    let estimate = machine.estimate(runs, &mut ())?;
+    let (low, high) = estimate.interval(1.96);
+    println!(
+        "accepted {} of {} runs: probability {:.4}, 95% confidence interval [{low:.4}, {high:.4}]",
+        estimate.accepted,
+        estimate.runs,
+        estimate.probability()
+    );
+    if estimate.undefined > 0 || estimate.unfinished > 0 {
+        println!(
+            "rejected {}, halted without a transition {}, did not halt {}",
+            estimate.rejected, estimate.undefined, estimate.unfinished
+        );
     }
+    Ok(estimate.verdict())
+}
 
-    Ok(exit_code.into())
+/// Searches the computation tree and prints the accepting branch, if any,
+/// the way a deterministic run is traced.
+fn search(
+    machine: &mut Machine,
+    input: &str,
+    strategy: Strategy,
+    limits: Limits,
+    observer: &mut impl Observer,
+) -> Result<Verdict, RunError> {
+    machine.load(input)?;
+    let search = machine.search(strategy, limits, observer)?;
+    for step in &search.path {
+        println!("{}", machine.render(&step.configuration));
+    }
+    eprintln!("{strategy}: {}", search.statistics);
+    Ok(search.verdict)
 }
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::{Configuration, Machine, State, Symbol, Transition, Verdict, search::Leaf, tape::Cell};
+
+/// Hooks into the events of a run, see [`Machine::execute`], or of a
+/// search, see [`Machine::search`]. Every hook does nothing by default.
+pub trait Observer {
+    /// Called before the first step, with the machine in its initial
+    /// configuration.
+    fn start(&mut self, _machine: &Machine) {}
+
+    /// Called after each step with the state and the symbols it started
+    /// from and the transition it followed.
+    fn transition(
+        &mut self,
+        _machine: &Machine,
+        _from: (State, &[Symbol]),
+        _transition: &Transition,
+    ) {
+    }
+
+    /// Called when a tape has to store a cell it never stored before.
+    fn grow(&mut self, _machine: &Machine, _tape: usize, _cell: Cell) {}
+
+    /// Called when no transition applies to the current configuration.
+    fn halt(&mut self, _machine: &Machine, _verdict: Verdict) {}
+
+    /// Called by a search for every configuration it reaches, with the
+    /// node it was reached from and the transition it followed. Nodes are
+    /// numbered in the order they are reached, from 0 for the initial
+    /// configuration, which has no parent. A search that starts over, like
+    /// iterative deepening, numbers them from 0 again.
+    fn node(
+        &mut self,
+        _machine: &Machine,
+        _node: usize,
+        _parent: Option<(usize, &Transition)>,
+        _config: &Configuration,
+    ) {
+    }
+
+    /// Called when a search won't follow a node any further.
+    fn leaf(&mut self, _machine: &Machine, _node: usize, _leaf: Leaf) {}
+}
+
+impl Observer for () {}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn start(&mut self, machine: &Machine) {
+        (**self).start(machine);
+    }
+
+    fn transition(&mut self, machine: &Machine, from: (State, &[Symbol]), transition: &Transition) {
+        (**self).transition(machine, from, transition);
+    }
+
+    fn grow(&mut self, machine: &Machine, tape: usize, cell: Cell) {
+        (**self).grow(machine, tape, cell);
+    }
+
+    fn halt(&mut self, machine: &Machine, verdict: Verdict) {
+        (**self).halt(machine, verdict);
+    }
+
+    fn node(
+        &mut self,
+        machine: &Machine,
+        node: usize,
+        parent: Option<(usize, &Transition)>,
+        config: &Configuration,
+    ) {
+        (**self).node(machine, node, parent, config);
+    }
+
+    fn leaf(&mut self, machine: &Machine, node: usize, leaf: Leaf) {
+        (**self).leaf(machine, node, leaf);
+    }
+}
+
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn start(&mut self, machine: &Machine) {
+        self.0.start(machine);
+        self.1.start(machine);
+    }
+
+    fn transition(&mut self, machine: &Machine, from: (State, &[Symbol]), transition: &Transition) {
+        self.0.transition(machine, from, transition);
+        self.1.transition(machine, from, transition);
+    }
+
+    fn grow(&mut self, machine: &Machine, tape: usize, cell: Cell) {
+        self.0.grow(machine, tape, cell);
+        self.1.grow(machine, tape, cell);
+    }
+
+    fn halt(&mut self, machine: &Machine, verdict: Verdict) {
+        self.0.halt(machine, verdict);
+        self.1.halt(machine, verdict);
+    }
+
+    fn node(
+        &mut self,
+        machine: &Machine,
+        node: usize,
+        parent: Option<(usize, &Transition)>,
+        config: &Configuration,
+    ) {
+        self.0.node(machine, node, parent, config);
+        self.1.node(machine, node, parent, config);
+    }
+
+    fn leaf(&mut self, machine: &Machine, node: usize, leaf: Leaf) {
+        self.0.leaf(machine, node, leaf);
+        self.1.leaf(machine, node, leaf);
+    }
+}
+
+/// Prints every configuration of the run in describe notation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tracer;
+
+impl Observer for Tracer {
+    fn start(&mut self, machine: &Machine) {
+        println!("{}", machine.describe());
+    }
+
+    fn transition(
+        &mut self,
+        machine: &Machine,
+        _from: (State, &[Symbol]),
+        _transition: &Transition,
+    ) {
+        println!("{}", machine.describe());
+    }
+}
+
+/// Counts the steps of a run, how often each transition was taken and how
+/// many cells the tape had to grow by.
+#[derive(Clone, Debug, Default)]
+pub struct Counter {
+    pub steps: u64,
+    pub cells: u64,
+    pub halts: u64,
+    pub transitions: HashMap<(State, Box<[Symbol]>), u64>,
+}
+
+impl Observer for Counter {
+    fn transition(
+        &mut self,
+        _machine: &Machine,
+        from: (State, &[Symbol]),
+        _transition: &Transition,
+    ) {
+        self.steps += 1;
+        *self.transitions.entry((from.0, from.1.into())).or_default() += 1;
+    }
+
+    fn grow(&mut self, _machine: &Machine, _tape: usize, _cell: Cell) {
+        self.cells += 1;
+    }
+
+    fn halt(&mut self, _machine: &Machine, _verdict: Verdict) {
+        self.halts += 1;
+    }
+}
@@ -0,0 +1,1271 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, hash_map::Entry},
+    str::Chars,
+};
+
+use crate::{
+    Alphabet, Direction, Probabilities, State, Symbol, Transitions,
+    build::{Builder, is_state_char, parse_probability, share},
+    diagnostic::Diagnostic,
+    error::{BuildError, Error},
+    intern::Interner,
+};
+
+#[derive(Debug, Default)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Description {
+    pub metadata: Metadata,
+    pub tapes: usize,
+    pub states: Interner,
+    pub symbols: Interner,
+    pub alphabet: Alphabet,
+    pub blank: Symbol,
+    pub accepting: HashSet<State>,
+    pub rejecting: HashSet<State>,
+    pub init_state: State,
+    pub transitions: Transitions,
+    pub nondeterministic: bool,
+    pub probabilistic: bool,
+    pub probabilities: Probabilities,
+}
+
+const KEYS: [&str; 12] = [
+    "tapes",
+    "tape-alphabet",
+    "alphabet",
+    "blank",
+    "reject",
+    "accept",
+    "start",
+    "nondeterministic",
+    "probabilistic",
+    "name",
+    "description",
+    "author",
+];
+
+const REQUIRED: [&str; 3] = ["alphabet", "blank", "start"];
+
+const POSITIONAL: [(&str, &str); 4] = [
+    ("alphabet", "no alphabet"),
+    ("blank", "no line for blank character"),
+    ("accept", "no line for accepting states"),
+    ("start", "no line for initial state"),
+];
+
+const WILDCARD: &str = "*";
+
+#[derive(Clone)]
+enum Read {
+    Symbol(Symbol),
+    Class(BTreeSet<Symbol>),
+    Any,
+}
+
+impl Read {
+    fn precedence(&self) -> usize {
+        match self {
+            Self::Symbol(_) => 2,
+            Self::Class(_) => 1,
+            Self::Any => 0,
+        }
+    }
+
+    fn symbols(&self, count: usize) -> Vec<Symbol> {
+        match self {
+            Self::Symbol(s) => vec![*s],
+            Self::Class(class) => class.iter().copied().collect(),
+            Self::Any => (0..count).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Write<'a> {
+    Symbol(Symbol),
+    Keep,
+    Variable(&'a str),
+}
+
+struct Rule<'a> {
+    line: Line<'a>,
+    token: Token<'a>,
+    state: &'a str,
+    param: Option<&'a str>,
+    vars: Vec<Option<&'a str>>,
+    reads: Vec<Read>,
+    next: &'a str,
+    writes: Vec<Write<'a>>,
+    dirs: Box<[Direction]>,
+    probability: Option<f64>,
+}
+
+type Bindings<'a> = Vec<(&'a str, Symbol)>;
+/// The rules chosen for each state and symbols, with their bindings.
+type Chosen<'a> = HashMap<(State, Box<[Symbol]>), Vec<(usize, Bindings<'a>)>>;
+
+impl<'a> Rule<'a> {
+    fn precedence(&self) -> (bool, usize) {
+        let reads = self.reads.iter().map(Read::precedence).sum();
+        (self.param.is_none(), reads)
+    }
+
+    /// Binds the variables of the rule, or returns `None` if the same
+    /// variable would be bound to different symbols.
+    fn bindings(&self, param: Option<Symbol>, reads: &[Symbol]) -> Option<Bindings<'a>> {
+        let mut bindings = Bindings::new();
+        let vars = self
+            .vars
+            .iter()
+            .zip(reads)
+            .filter_map(|(v, &s)| v.zip(Some(s)));
+        for (name, symbol) in self.param.zip(param).into_iter().chain(vars) {
+            match bindings.iter().find(|&&(n, _)| n == name) {
+                Some(&(_, bound)) if bound != symbol => return None,
+                Some(_) => {}
+                None => bindings.push((name, symbol)),
+            }
+        }
+        Some(bindings)
+    }
+}
+
+/// Every combination of one symbol from each set.
+fn product(sets: &[Vec<Symbol>]) -> Vec<Box<[Symbol]>> {
+    sets.iter()
+        .fold(vec![Vec::new()], |combinations, set| {
+            combinations
+                .iter()
+                .flat_map(|prefix| set.iter().map(|&s| [prefix.as_slice(), &[s]].concat()))
+                .collect()
+        })
+        .into_iter()
+        .map(Vec::into_boxed_slice)
+        .collect()
+}
+
+/// The name of the field at `index` in a transition on `tapes` tapes.
+fn field_name(index: usize, tapes: usize) -> String {
+    let (name, tape) = match index {
+        0 => return "current state".to_owned(),
+        i if i <= tapes => ("head symbol", i),
+        i if i == tapes + 1 => return "next state".to_owned(),
+        i if i <= 2 * tapes + 1 => ("write symbol", i - tapes - 1),
+        i => ("direction", i - 2 * tapes - 1),
+    };
+    match tapes {
+        1 => name.to_owned(),
+        _ => format!("{name} for tape {tape}"),
+    }
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+fn segments(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        let after = &rest[start + 1..];
+        let (name, end) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !is_variable_char(c))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        if name.is_empty() {
+            segments.push(Segment::Text(&rest[..start + 1]));
+        } else {
+            segments.push(Segment::Text(&rest[..start]));
+            segments.push(Segment::Variable(name));
+        }
+        rest = &after[end..];
+    }
+    segments.push(Segment::Text(rest));
+    segments
+}
+
+/// Fills the variables of a state name template with the names of the
+/// symbols bound to them. A character that can't be in a state name is
+/// written as its code in hexadecimal between dots, so the name reads back.
+fn substitute(template: &str, bindings: &Bindings, symbols: &Interner) -> String {
+    let mut state = String::new();
+    for segment in segments(template) {
+        let symbol = match segment {
+            Segment::Text(text) => {
+                state.push_str(text);
+                continue;
+            }
+            Segment::Variable(name) => bindings
+                .iter()
+                .find(|&&(n, _)| n == name)
+                .map_or("", |&(_, s)| symbols.name(s)),
+        };
+        for c in symbol.chars() {
+            match c {
+                c if is_state_char(c) => state.push(c),
+                c => state.push_str(&format!(".{:x}.", c as u32)),
+            }
+        }
+    }
+    state
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    column: usize,
+    start: usize,
+    text: &'a str,
+}
+
+impl<'a> Token<'a> {
+    fn slice(&self, start: usize, end: usize) -> Token<'a> {
+        Token {
+            column: self.column + self.text[..start].chars().count(),
+            start: self.start + start,
+            text: &self.text[start..end],
+        }
+    }
+}
+
+fn unquote(raw: &str) -> Result<String, String> {
+    let mut name = String::new();
+    let mut chars = raw.chars();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            '\\' if quoted => name.push(escape(&mut chars)?),
+            c => name.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_owned());
+    }
+    Ok(name)
+}
+
+fn escape(chars: &mut Chars) -> Result<char, String> {
+    let code = |digits: &str| {
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("invalid character code `{digits}`"))
+    };
+    Ok(match chars.next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some('\\') => '\\',
+        Some('\'') => '\'',
+        Some('x') => {
+            let digits = chars.as_str().get(..2);
+            let Some(digits) = digits.filter(|d| d.chars().all(|c| c.is_ascii_hexdigit())) else {
+                return Err("`\\x` needs two hexadecimal digits".to_owned());
+            };
+            let c = code(digits)?;
+            chars.nth(1);
+            c
+        }
+        Some('u') if chars.next() == Some('{') => {
+            code(&chars.by_ref().take_while(|&c| c != '}').collect::<String>())?
+        }
+        Some(c) => return Err(format!("unknown escape sequence `\\{c}`")),
+        None => return Err("unterminated escape sequence".to_owned()),
+    })
+}
+
+fn split_class(body: &str) -> Vec<(usize, usize)> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push((start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push((start, body.len()));
+    items
+}
+
+impl<'a> Line<'a> {
+    fn tokens(&self) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        let mut quoted = false;
+        let mut escaped = false;
+        let mut push = |column, start, end| {
+            tokens.push(Token {
+                column,
+                start,
+                text: &self.text[start..end],
+            })
+        };
+        for (column, (i, c)) in self.text.char_indices().enumerate() {
+            match start {
+                _ if escaped => escaped = false,
+                Some(_) if quoted => match c {
+                    '\\' => escaped = true,
+                    '\'' => quoted = false,
+                    _ => {}
+                },
+                Some((column, s)) if c.is_whitespace() => {
+                    push(column, s, i);
+                    start = None;
+                }
+                Some(_) => quoted = c == '\'',
+                None if c == '#' => break,
+                None if !c.is_whitespace() => {
+                    start = Some((column + 1, i));
+                    quoted = c == '\'';
+                }
+                None => {}
+            }
+        }
+        if let Some((column, s)) = start {
+            push(column, s, self.text.len());
+        }
+        tokens
+    }
+
+    /// A field whose key is the first token of the line. Its text, the
+    /// value of the metadata keys, is the raw rest of the line, so a `#` in
+    /// it doesn't start a comment.
+    fn field(&self, name: &'a str, key: Token<'a>, mut tokens: Vec<Token<'a>>) -> Field<'a> {
+        let values = tokens.split_off(1);
+        let text = self.text[tokens[0].start + tokens[0].text.len()..].trim();
+        Field {
+            line: *self,
+            name,
+            key,
+            values,
+            text,
+        }
+    }
+
+    fn keyed(&self) -> Option<Field<'a>> {
+        let tokens = self.tokens();
+        let name = tokens[0].text.strip_suffix(':')?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+            return None;
+        }
+        let key = tokens[0].slice(0, name.len());
+        Some(self.field(name, key, tokens))
+    }
+
+    /// The key of a `%<key>` directive line, which may not be a known key.
+    /// A lone `%` is a symbol, not a directive.
+    fn directive_key(&self) -> Option<Token<'a>> {
+        let first = *self.tokens().first()?;
+        first.text.strip_prefix('%')?;
+        let key = first.slice(1, first.text.len());
+        let valid =
+            !key.text.is_empty() && key.text.chars().all(|c| c.is_ascii_lowercase() || c == '-');
+        valid.then_some(key)
+    }
+
+    fn directive(&self) -> Field<'a> {
+        let tokens = self.tokens();
+        let key = tokens[0].slice(1, tokens[0].text.len());
+        self.field(key.text, key, tokens)
+    }
+
+    fn positional(&self, name: &'a str) -> Field<'a> {
+        let tokens = self.tokens();
+        Field {
+            line: *self,
+            name,
+            key: tokens[0],
+            values: tokens,
+            text: "",
+        }
+    }
+
+    fn end(&self) -> Token<'a> {
+        Token {
+            column: self.text.chars().count() + 1,
+            start: self.text.len(),
+            text: "",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Field<'a> {
+    line: Line<'a>,
+    name: &'a str,
+    key: Token<'a>,
+    values: Vec<Token<'a>>,
+    text: &'a str,
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    last: Line<'a>,
+    builder: Builder,
+    fields: HashMap<&'a str, Field<'a>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    fn diagnostic(
+        &self,
+        line: Line<'a>,
+        token: Token<'a>,
+        message: impl Into<String>,
+    ) -> Diagnostic {
+        Diagnostic::new(
+            self.file,
+            line.number,
+            token.column,
+            token.text,
+            line.text,
+            message,
+        )
+    }
+
+    fn error(&mut self, line: Line<'a>, token: Token<'a>, message: impl Into<String>) {
+        self.diagnostics.push(self.diagnostic(line, token, message));
+    }
+
+    fn report<T>(
+        &mut self,
+        line: Line<'a>,
+        token: Token<'a>,
+        result: Result<T, BuildError>,
+    ) -> Option<T> {
+        result
+            .map_err(|e| self.error(line, token, e.to_string()))
+            .ok()
+    }
+
+    fn field(&mut self, field: Field<'a>) {
+        if !KEYS.contains(&field.name) {
+            let message = format!("unknown field `{}`", field.name);
+            self.error(field.line, field.key, message);
+            return;
+        }
+        match self.fields.get(field.name) {
+            Some(first) => {
+                let note = self.diagnostic(first.line, first.key, "first defined here");
+                let message = format!(
+                    "`{}` is already defined on line {}",
+                    field.name, first.line.number
+                );
+                let error = self.diagnostic(field.line, field.key, message);
+                self.diagnostics.push(error.with_note(note));
+            }
+            None => _ = self.fields.insert(field.name, field),
+        }
+    }
+
+    fn take(&mut self, name: &str, keyed: bool) -> Option<Field<'a>> {
+        let field = self.fields.remove(name);
+        if field.is_some() {
+            return field;
+        }
+        if keyed {
+            if REQUIRED.contains(&name) {
+                self.missing(&format!("missing `{name}:` field"));
+            }
+        } else if let Some((_, message)) = POSITIONAL.iter().find(|(n, _)| *n == name) {
+            self.missing(message);
+        }
+        field
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        let Some(field) = self.fields.remove(name) else {
+            return false;
+        };
+        self.unexpected(field.line, field.values.get(1));
+        match field.values.first().map(|t| t.text) {
+            None | Some("true") => true,
+            Some("false") => false,
+            Some(value) => {
+                let message = format!("invalid value `{value}`, expected `true` or `false`");
+                self.error(field.line, field.values[0], message);
+                false
+            }
+        }
+    }
+
+    fn text(&mut self, name: &str) {
+        if let Some(field) = self.fields.remove(name) {
+            let result = self.builder.text(name, field.text);
+            self.report(field.line, field.line.end(), result);
+        }
+    }
+
+    fn missing(&mut self, message: &str) {
+        let line = self.last;
+        self.error(line, line.end(), message);
+    }
+
+    fn unexpected(&mut self, line: Line<'a>, token: Option<&Token<'a>>) {
+        if let Some(&token) = token {
+            self.error(line, token, format!("unexpected token `{}`", token.text));
+        }
+    }
+
+    fn single(&mut self, field: &Field<'a>, message: &str) -> Option<Token<'a>> {
+        if field.values.is_empty() {
+            self.error(field.line, field.line.end(), message);
+        }
+        self.unexpected(field.line, field.values.get(1));
+        field.values.first().copied()
+    }
+
+    fn unquote(&mut self, line: Line<'a>, token: Token<'a>) -> Option<String> {
+        match unquote(token.text) {
+            Ok(name) if name.is_empty() => {
+                self.error(line, token, "empty symbol");
+                None
+            }
+            Ok(name) => Some(name),
+            Err(e) => {
+                self.error(line, token, e);
+                None
+            }
+        }
+    }
+
+    fn symbol(&mut self, line: Line<'a>, token: Token<'a>, what: &str) -> Option<Symbol> {
+        let name = self.unquote(line, token)?;
+        let symbol = self.builder.symbols.get(&name);
+        if symbol.is_none() {
+            self.error(
+                line,
+                token,
+                format!(
+                    "invalid {what} symbol `{}`, doesn't exist in the alphabet",
+                    token.text
+                ),
+            );
+        }
+        symbol
+    }
+
+    fn template(
+        &mut self,
+        line: Line<'a>,
+        token: Token<'a>,
+        message: &str,
+    ) -> Option<Vec<&'a str>> {
+        let mut variables = Vec::new();
+        for segment in segments(token.text) {
+            match segment {
+                Segment::Text(text) if !text.chars().all(is_state_char) => {
+                    self.error(line, token, format!("{message} `{}`", token.text));
+                    return None;
+                }
+                Segment::Variable(name) => variables.push(name),
+                _ => {}
+            }
+        }
+        Some(variables)
+    }
+
+    fn unbound(&mut self, line: Line<'a>, token: Token<'a>, name: &str, bound: &[&str]) -> bool {
+        if bound.contains(&name) {
+            return false;
+        }
+        self.error(line, token, format!("unbound variable `${name}`"));
+        true
+    }
+
+    fn read(&mut self, line: Line<'a>, token: Token<'a>) -> Option<Read> {
+        if token.text == WILDCARD {
+            return Some(Read::Any);
+        }
+        let (negated, start) = match token.text {
+            t if t.starts_with("!{") => (true, 2),
+            t if t.starts_with('{') => (false, 1),
+            _ => return self.symbol(line, token, "head").map(Read::Symbol),
+        };
+        let Some(body) = token.text[start..].strip_suffix('}') else {
+            self.error(line, token, "unterminated symbol class");
+            return None;
+        };
+        let mut class = BTreeSet::new();
+        let mut valid = true;
+        for (from, to) in split_class(body) {
+            match self.symbol(line, token.slice(start + from, start + to), "class") {
+                Some(symbol) => _ = class.insert(symbol),
+                None => valid = false,
+            }
+        }
+        if negated {
+            class = (0..self.builder.symbols.len())
+                .filter(|s| !class.contains(s))
+                .collect();
+        }
+        if valid && class.is_empty() {
+            self.error(line, token, "symbol class doesn't match any symbol");
+            valid = false;
+        }
+        valid.then_some(Read::Class(class))
+    }
+
+    fn pattern(&mut self, line: Line<'a>, token: Token<'a>) -> Option<(Read, Option<&'a str>)> {
+        let symbols = &self.builder.symbols;
+        let literal = Some(token.text).filter(|&t| t != WILDCARD);
+        if let Some(symbol) = literal
+            .and_then(|t| unquote(t).ok())
+            .and_then(|s| symbols.get(&s))
+        {
+            return Some((Read::Symbol(symbol), None));
+        }
+        if !token.text.starts_with('$') {
+            return self.read(line, token).map(|read| (read, None));
+        }
+        let end = token.text.find(':').unwrap_or(token.text.len());
+        let var = token.slice(1, end);
+        if var.text.is_empty() || !var.text.chars().all(is_variable_char) {
+            self.error(line, var, format!("invalid variable name `{}`", var.text));
+            return None;
+        }
+        if end == token.text.len() {
+            return Some((Read::Any, Some(var.text)));
+        }
+        let pattern = token.slice(end + 1, token.text.len());
+        if pattern.text.is_empty() {
+            self.error(line, pattern, "missing pattern after the variable");
+            return None;
+        }
+        self.read(line, pattern).map(|read| (read, Some(var.text)))
+    }
+
+    fn alphabet_symbol(&mut self, line: Line<'a>, token: Token<'a>, what: &str) -> Option<Symbol> {
+        if token.text == WILDCARD {
+            self.error(line, token, "`*` is reserved for wildcard transitions");
+            return None;
+        }
+        let name = self.unquote(line, token)?;
+        let result = match what {
+            "tape" => self.builder.tape_symbol(&name),
+            "input" => self.builder.input_symbol(&name),
+            _ => self.builder.blank(&name),
+        };
+        self.report(line, token, result)
+    }
+
+    fn rule(&mut self, line: Line<'a>) -> Option<Rule<'a>> {
+        if let Some(key) = line.directive_key() {
+            self.error(line, key, format!("unknown field `{}`", key.text));
+            return None;
+        }
+        let tapes = self.builder.tapes;
+        let tokens = line.tokens();
+        let Some(fields) = tapes.checked_mul(3).and_then(|n| n.checked_add(2)) else {
+            self.error(
+                line,
+                line.end(),
+                format!("too many tapes for a transition: {tapes}"),
+            );
+            return None;
+        };
+        if tokens.len() < fields {
+            let field = field_name(tokens.len(), tapes);
+            self.error(line, line.end(), format!("the {field} was not specified"));
+        }
+        let probability = match tokens.get(fields) {
+            Some(&token) if self.builder.probabilistic => {
+                self.unexpected(line, tokens.get(fields + 1));
+                // The rule is kept without an invalid probability, so the
+                // sum of its group doesn't report the missing one again.
+                let result = parse_probability(token.text);
+                self.report(line, token, result)
+            }
+            token => {
+                self.unexpected(line, token);
+                None
+            }
+        };
+        let patterns = (1..=tapes)
+            .map(|i| tokens.get(i).and_then(|&t| self.pattern(line, t)))
+            .collect::<Vec<_>>();
+        let state = tokens
+            .first()
+            .and_then(|&t| self.template(line, t, "invalid state name"));
+        let mut param = None;
+        for &name in state.iter().flatten() {
+            if param.is_some_and(|p| p != name) {
+                self.error(line, tokens[0], "a state name can only use one variable");
+            }
+            param = Some(name);
+        }
+        let (reads, vars): (Vec<_>, Vec<_>) = patterns.into_iter().flatten().unzip();
+        let bound = param
+            .into_iter()
+            .chain(vars.iter().flatten().copied())
+            .collect::<Vec<_>>();
+        let resolved = reads.len() == tapes && state.is_some();
+        let next_state = tokens.get(tapes + 1).and_then(|&t| {
+            let variables = self.template(line, t, "invalid next state name")?;
+            let unbound = resolved
+                && variables
+                    .into_iter()
+                    .any(|name| self.unbound(line, t, name, &bound));
+            (!unbound).then_some(t.text)
+        });
+        let writes = (tapes + 2..2 * tapes + 2)
+            .map(|i| {
+                let &t = tokens.get(i)?;
+                match t.text {
+                    WILDCARD => Some(Write::Keep),
+                    text if text.starts_with('$') && self.builder.symbols.get(text).is_none() => {
+                        let unbound = resolved && self.unbound(line, t, &text[1..], &bound);
+                        (!unbound).then_some(Write::Variable(&text[1..]))
+                    }
+                    _ => self.symbol(line, t, "write").map(Write::Symbol),
+                }
+            })
+            .collect::<Vec<_>>();
+        let dirs = (2 * tapes + 2..fields)
+            .map(|i| {
+                let &t = tokens.get(i)?;
+                let dir = Direction::try_from(t.text);
+                if let Err(e) = dir {
+                    self.error(line, t, format!("{e} `{}`", t.text));
+                }
+                dir.ok()
+            })
+            .collect::<Vec<_>>();
+        Some(Rule {
+            line,
+            token: *tokens.get(1)?,
+            state: state.and(tokens.first())?.text,
+            param,
+            vars,
+            reads: Some(reads).filter(|_| resolved)?,
+            next: next_state?,
+            writes: writes.into_iter().collect::<Option<_>>()?,
+            dirs: dirs.into_iter().collect::<Option<_>>()?,
+            probability,
+        })
+    }
+
+    /// Formats the symbols read by a transition for a diagnostic.
+    fn reads_name(&self, reads: &[Symbol]) -> String {
+        let names = reads
+            .iter()
+            .map(|&s| self.builder.symbols.name(s))
+            .collect::<Vec<_>>();
+        match names.as_slice() {
+            [name] => format!("`{name}`"),
+            names => format!("`({})`", names.join(", ")),
+        }
+    }
+
+    /// Checks that the probabilities of the choices for each state and
+    /// symbols add up to 1, once for every group of rules, and hands them
+    /// to the builder.
+    fn probabilities(&mut self, rules: &[Rule<'a>], chosen: &Chosen<'a>) {
+        let mut errors = BTreeMap::<Vec<usize>, (f64, BTreeSet<Box<[Symbol]>>)>::new();
+        for (key, choices) in chosen {
+            let given = choices
+                .iter()
+                .map(|&(i, _)| rules[i].probability)
+                .collect::<Vec<_>>();
+            if let Err(sum) = share(&given) {
+                let group = choices.iter().map(|&(i, _)| i).collect();
+                let error = errors.entry(group).or_insert((sum, BTreeSet::new()));
+                error.1.insert(key.1.clone());
+            }
+            self.builder.probabilities.insert(key.clone(), given);
+        }
+        for (group, (sum, reads)) in errors {
+            let reads = reads
+                .iter()
+                .map(|reads| self.reads_name(reads))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let first = &rules[group[0]];
+            let message =
+                format!("the probabilities of the transitions on {reads} add up to {sum}, not 1");
+            let mut error = self.diagnostic(first.line, first.token, message);
+            for &i in &group[1..] {
+                let rule = &rules[i];
+                error = error.with_note(self.diagnostic(rule.line, rule.token, "another choice"));
+            }
+            self.diagnostics.push(error);
+        }
+    }
+
+    fn expand(&mut self, rules: &[Rule<'a>]) -> Transitions {
+        let count = self.builder.symbols.len();
+        let mut chosen = Chosen::new();
+        let mut conflicts = BTreeMap::<(usize, usize), BTreeSet<Box<[Symbol]>>>::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let params = match rule.param {
+                Some(_) => (0..count).map(Some).collect(),
+                None => vec![None],
+            };
+            let sets = rule
+                .reads
+                .iter()
+                .map(|read| read.symbols(count))
+                .collect::<Vec<_>>();
+            let combinations = product(&sets);
+            for param in params {
+                for reads in &combinations {
+                    let Some(bindings) = rule.bindings(param, reads) else {
+                        continue;
+                    };
+                    let state = substitute(rule.state, &bindings, &self.builder.symbols);
+                    let state = self.builder.states.intern(&state);
+                    match chosen.entry((state, reads.clone())) {
+                        Entry::Vacant(e) => _ = e.insert(vec![(i, bindings)]),
+                        Entry::Occupied(mut e) => {
+                            let other = e.get()[0].0;
+                            match rule.precedence().cmp(&rules[other].precedence()) {
+                                Ordering::Less => {}
+                                Ordering::Equal => {
+                                    if !self.builder.nondeterministic && !self.builder.probabilistic
+                                    {
+                                        let reads = reads.clone();
+                                        conflicts.entry((other, i)).or_default().insert(reads);
+                                    }
+                                    e.get_mut().push((i, bindings));
+                                }
+                                Ordering::Greater => _ = e.insert(vec![(i, bindings)]),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for ((first, second), reads) in conflicts {
+            let reads = reads
+                .iter()
+                .map(|reads| self.reads_name(reads))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let (first, second) = (&rules[first], &rules[second]);
+            let message = if second.reads.iter().any(|r| matches!(r, Read::Class(_))) {
+                format!(
+                    "symbol class overlaps with the one on line {} on {reads}",
+                    first.line.number
+                )
+            } else {
+                format!(
+                    "transition on {reads} is already defined on line {}",
+                    first.line.number
+                )
+            };
+            let note = self.diagnostic(first.line, first.token, "first defined here");
+            let error = self.diagnostic(second.line, second.token, message);
+            self.diagnostics.push(error.with_note(note));
+        }
+        if self.builder.probabilistic {
+            self.probabilities(rules, &chosen);
+        }
+        chosen
+            .into_iter()
+            .map(|((state, reads), choices)| {
+                let choices = choices
+                    .into_iter()
+                    .map(|(i, bindings)| {
+                        let rule = &rules[i];
+                        let next = substitute(rule.next, &bindings, &self.builder.symbols);
+                        let writes = rule
+                            .writes
+                            .iter()
+                            .zip(&reads)
+                            .map(|(write, &read)| match *write {
+                                Write::Symbol(s) => s,
+                                Write::Keep => read,
+                                Write::Variable(name) => bindings
+                                    .iter()
+                                    .find(|&&(n, _)| n == name)
+                                    .map_or(read, |&(_, s)| s),
+                            })
+                            .collect();
+                        (self.builder.states.intern(&next), writes, rule.dirs.clone())
+                    })
+                    .collect();
+                ((state, reads), choices)
+            })
+            .collect()
+    }
+}
+
+pub fn parse(file: &str, source: &str) -> Result<Description, Error> {
+    let (directives, lines): (Vec<_>, Vec<_>) = source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| Line {
+            number: i + 1,
+            text,
+        })
+        .filter(|l| !l.tokens().is_empty())
+        .partition(|l| {
+            l.directive_key()
+                .is_some_and(|key| KEYS.contains(&key.text))
+        });
+    let mut lines = lines.into_iter().peekable();
+    let mut parser = Parser {
+        file,
+        last: Line {
+            number: source.lines().count().max(1),
+            text: source.lines().last().unwrap_or_default(),
+        },
+        builder: Builder::new(),
+        fields: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    for line in directives {
+        parser.field(line.directive());
+    }
+
+    let version = lines
+        .peek()
+        .and_then(Line::keyed)
+        .filter(|f| f.name == "version");
+    let keyed = match version {
+        Some(field) => {
+            lines.next();
+            match parser.single(&field, "you should specify the format version") {
+                Some(t) if t.text == "1" => false,
+                Some(t) if t.text == "2" => true,
+                Some(t) => {
+                    let message = format!("unsupported format version `{}`", t.text);
+                    parser.error(field.line, t, message);
+                    true
+                }
+                None => true,
+            }
+        }
+        None => false,
+    };
+    if keyed {
+        while let Some(field) = lines.peek().and_then(Line::keyed) {
+            parser.field(field);
+            lines.next();
+        }
+    } else {
+        for (name, _) in POSITIONAL {
+            if let Some(line) = lines.next() {
+                parser.field(line.positional(name));
+            }
+        }
+    }
+
+    if let Some(field) = parser.fields.remove("tapes")
+        && let Some(token) = parser.single(&field, "you should specify the number of tapes")
+    {
+        match token.text.parse() {
+            Ok(tapes) => {
+                let result = parser.builder.tapes(tapes);
+                parser.report(field.line, token, result);
+            }
+            Err(_) => {
+                let message = format!("invalid number of tapes `{}`", token.text);
+                parser.error(field.line, token, message);
+            }
+        }
+    }
+    if let Some(field) = parser.fields.remove("tape-alphabet") {
+        if field.values.is_empty() {
+            parser.error(
+                field.line,
+                field.line.end(),
+                "you should specify the tape alphabet",
+            );
+        }
+        for &token in &field.values {
+            parser.alphabet_symbol(field.line, token, "tape");
+        }
+        parser.builder.tape_alphabet.get_or_insert_default();
+    }
+    if let Some(field) = parser.take("alphabet", keyed) {
+        for &token in &field.values {
+            parser.alphabet_symbol(field.line, token, "input");
+        }
+    }
+    if let Some(field) = parser.take("blank", keyed)
+        && let Some(token) = parser.single(&field, "you should specify a blank symbol")
+    {
+        parser.alphabet_symbol(field.line, token, "blank");
+    }
+    for (name, add) in [
+        ("reject", Builder::reject as fn(&mut Builder, &str) -> _),
+        ("accept", Builder::accept),
+    ] {
+        if let Some(field) = parser.take(name, keyed) {
+            for &token in &field.values {
+                let result = add(&mut parser.builder, token.text);
+                parser.report(field.line, token, result);
+            }
+        }
+    }
+    if let Some(field) = parser.take("start", keyed)
+        && let Some(token) = parser.single(&field, "you should specify a initial state")
+    {
+        let result = parser.builder.start(token.text);
+        parser.report(field.line, token, result);
+    }
+    let flags = ["nondeterministic", "probabilistic"].map(|name| parser.fields.get(name).cloned());
+    let nondeterministic = parser.flag("nondeterministic");
+    parser.builder.nondeterministic(nondeterministic);
+    let probabilistic = parser.flag("probabilistic");
+    parser.builder.probabilistic(probabilistic);
+    if nondeterministic
+        && probabilistic
+        && let [Some(mut first), Some(mut second)] = flags
+    {
+        if first.line.number > second.line.number {
+            (first, second) = (second, first);
+        }
+        let note = format!("`{}` is set here", first.name);
+        let note = parser.diagnostic(first.line, first.key, note);
+        let message = BuildError::NondeterministicAndProbabilistic.to_string();
+        let error = parser.diagnostic(second.line, second.key, message);
+        parser.diagnostics.push(error.with_note(note));
+    }
+    for name in ["name", "description", "author"] {
+        parser.text(name);
+    }
+
+    let rules = lines
+        .filter_map(|line| parser.rule(line))
+        .collect::<Vec<_>>();
+    parser.builder.transitions = parser.expand(&rules);
+    parser.diagnostics.sort_by_key(|d| (d.line, d.column));
+
+    if !parser.diagnostics.is_empty() {
+        return Err(Error::Parse(parser.diagnostics));
+    }
+    Ok(parser.builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_keeps_hashes() {
+        let source = "version: 2
+name: Machine #1 for hw
+%author   someone # and someone else
+alphabet: a
+blank: _
+start: q0
+";
+        let metadata = parse("test", source).unwrap().metadata;
+        assert_eq!(metadata.name.as_deref(), Some("Machine #1 for hw"));
+        assert_eq!(
+            metadata.author.as_deref(),
+            Some("someone # and someone else")
+        );
+    }
+
+    #[test]
+    fn invalid_probability_reported_once() {
+        let source = "version: 2
+alphabet: a
+blank: _
+start: q
+accept: acc
+reject: rej
+probabilistic:
+q _ acc _ N 0.5
+q _ rej _ N 0.5x
+";
+        let Err(Error::Parse(diagnostics)) = parse("test", source) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (9, 13));
+    }
+
+    #[test]
+    fn nondeterministic_and_probabilistic() {
+        let source = "version: 2
+probabilistic:
+alphabet: a
+blank: _
+start: q
+%nondeterministic true
+";
+        let Err(Error::Parse(diagnostics)) = parse("test", source) else {
+            panic!("expected a parse error");
+        };
+        let [error] = diagnostics.as_slice() else {
+            panic!("expected one error, got {diagnostics:?}");
+        };
+        assert_eq!(
+            (error.line, error.column, &*error.token),
+            (6, 2, "nondeterministic")
+        );
+        assert_eq!(error.notes[0].line, 2);
+    }
+
+    #[test]
+    fn bare_star_is_the_wildcard() {
+        let source = "version: 2
+alphabet: a '*'
+blank: _
+start: q0
+q0 '*' q0 a R
+q0 * q1 * R
+";
+        let description = parse("test", source).unwrap();
+        let star = description.symbols.get("*").unwrap();
+        let a = description.symbols.get("a").unwrap();
+        let q0 = description.states.get("q0").unwrap();
+        let q1 = description.states.get("q1").unwrap();
+        let next = |read| description.transitions[&(q0, Box::from([read]))][0].0;
+        assert_eq!(next(star), q0);
+        assert_eq!(next(a), q1);
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert_eq!(unquote(r"'\x41\x7e'").unwrap(), "A~");
+        for raw in [r"'\x1'", r"'\x'", r"'\xg1'"] {
+            assert_eq!(
+                unquote(raw).unwrap_err(),
+                r"`\x` needs two hexadecimal digits",
+                "{raw}"
+            );
+        }
+    }
+
+    /// The state a single-tape machine goes to from `state` on `read`.
+    fn next(description: &Description, state: &str, read: &str) -> String {
+        let state = description.states.get(state).unwrap();
+        let read = description.symbols.get(read).unwrap();
+        let (next, _, _) = &description.transitions[&(state, Box::from([read]))][0];
+        description.states.name(*next).to_owned()
+    }
+
+    #[test]
+    fn symbol_beats_class_beats_wildcard() {
+        let source = "a b c
+_
+h
+q
+q * any * R
+q {a,b} class * R
+q a symbol * R
+";
+        let description = parse("test", source).unwrap();
+        assert_eq!(next(&description, "q", "a"), "symbol");
+        assert_eq!(next(&description, "q", "b"), "class");
+        assert_eq!(next(&description, "q", "c"), "any");
+        assert_eq!(next(&description, "q", "_"), "any");
+    }
+
+    #[test]
+    fn overlapping_classes() {
+        let source = "a b c
+_
+h
+q
+q {a,b} one * R
+q !{a,_} two * R
+";
+        let Err(Error::Parse(diagnostics)) = parse("test", source) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "symbol class overlaps with the one on line 5 on `b`"
+        );
+        assert_eq!(diagnostics[0].line, 6);
+    }
+
+    #[test]
+    fn v1_percent_symbol() {
+        let source = "a\n%\nacc\nq0\nq0 a q0 a R\nq0 % acc % N\n";
+        let description = parse("test", source).unwrap();
+        assert_eq!(description.symbols.name(description.blank), "%");
+        assert_eq!(next(&description, "q0", "%"), "acc");
+    }
+
+    #[test]
+    fn v1_reads_like_v2() {
+        let v1 = "# the positional format, without a version
+a b
+_
+acc
+q0
+%tape-alphabet a b _ X
+q0 a q0 X R
+q0 b stuck b N
+q0 _ acc _ N
+";
+        let v2 = "version: 2
+tape-alphabet: a b _ X
+alphabet: a b
+blank: _
+accept: acc
+start: q0
+q0 a q0 X R
+q0 b stuck b N
+q0 _ acc _ N
+";
+        let v1 = parse("v1", v1).unwrap();
+        assert_eq!(v1.to_string(), parse("v2", v2).unwrap().to_string());
+    }
+
+    #[test]
+    fn most_specific_tapes_win() {
+        let source = "version: 2
+tapes: 2
+alphabet: a
+blank: _
+start: q
+q * * any * * R R
+q a * first * * R R
+q a _ both * * R R
+";
+        let description = parse("test", source).unwrap();
+        let q = description.states.get("q").unwrap();
+        let [a, blank] = ["a", "_"].map(|s| description.symbols.get(s).unwrap());
+        let next = |reads: [Symbol; 2]| {
+            let (next, _, _) = &description.transitions[&(q, Box::from(reads))][0];
+            description.states.name(*next)
+        };
+        assert_eq!(next([a, blank]), "both");
+        assert_eq!(next([a, a]), "first");
+        assert_eq!(next([blank, a]), "any");
+    }
+}
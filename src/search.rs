@@ -0,0 +1,508 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use crate::{
+    Transition,
+    error::RuntimeError,
+    machine::{Configuration, Machine, Step, Verdict},
+    observe::Observer,
+};
+
+/// Bounds on the part of the computation tree a search explores.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Branches aren't followed past this many steps.
+    pub depth: Option<u64>,
+    /// The search stops after generating this many configurations.
+    pub configurations: Option<usize>,
+}
+
+/// The order in which the computation tree is explored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// One step deeper at a time, keeping the whole frontier in memory.
+    #[default]
+    BreadthFirst,
+    /// One branch at a time, keeping only the current branch in memory.
+    /// Without a depth limit it never leaves a branch that runs forever.
+    DepthFirst,
+    /// Depth-first with a bound of 0 steps, then 1, 2 and so on, which
+    /// finds the shortest accepting branch in the memory of a depth-first
+    /// search at the cost of exploring the top of the tree again.
+    IterativeDeepening,
+    /// Breadth-first, skipping configurations reached before.
+    Memoized,
+}
+
+impl TryFrom<&str> for Strategy {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(match value {
+            "breadth-first" => Self::BreadthFirst,
+            "depth-first" => Self::DepthFirst,
+            "iterative-deepening" => Self::IterativeDeepening,
+            "memoized" => Self::Memoized,
+            _ => Err("invalid search strategy")?,
+        })
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BreadthFirst => "breadth-first",
+            Self::DepthFirst => "depth-first",
+            Self::IterativeDeepening => "iterative-deepening",
+            Self::Memoized => "memoized",
+        })
+    }
+}
+
+/// Why a search didn't follow a node of the computation tree any further.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Leaf {
+    /// The node is in an accepting state.
+    Accepting,
+    /// No transition applies and the node isn't in an accepting state.
+    Rejecting,
+    /// The node is as deep as the depth limit allows.
+    CutOff,
+    /// The node was reached before, see [`Strategy::Memoized`].
+    Duplicate,
+}
+
+impl fmt::Display for Leaf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Accepting => "accepting",
+            Self::Rejecting => "rejecting",
+            Self::CutOff => "cut-off",
+            Self::Duplicate => "duplicate",
+        })
+    }
+}
+
+/// What a search cost, to compare strategies on the same machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Configurations generated, counting the initial one and every
+    /// configuration generated again by a later iteration.
+    pub explored: usize,
+    /// Configurations skipped because they were reached before.
+    pub duplicates: usize,
+    /// The most configurations kept in memory at once.
+    pub peak: usize,
+    /// The most steps taken by a branch.
+    pub depth: u64,
+    /// How many times the tree was explored from the initial configuration.
+    pub iterations: u64,
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "explored {} configuration(s), skipped {} duplicate(s), kept at most {} at once, \
+             {} step(s) deep, {} iteration(s)",
+            self.explored, self.duplicates, self.peak, self.depth, self.iterations
+        )
+    }
+}
+
+/// The outcome of a search through the computation tree.
+#[derive(Clone, Debug)]
+pub struct Search {
+    /// `Accept` if a branch reached an accepting state, `Reject` if every
+    /// branch halted without reaching one, `NeverAccepts` if a memoized
+    /// search went through every reachable configuration but skipped some
+    /// that can still move, and `SearchLimit` if the limits cut some branch
+    /// short before any of these happened.
+    pub verdict: Verdict,
+    /// The steps from the initial configuration to the accepting one.
+    pub path: Vec<Step>,
+    pub statistics: Statistics,
+}
+
+enum Outcome {
+    Accept(Vec<Step>),
+    Reject,
+    /// Every reachable configuration was seen without accepting, and some
+    /// branch came back to a configuration it had already been in.
+    Exhausted,
+    Cut,
+}
+
+/// A configuration on the current branch of a depth-first search, with
+/// its node number, the transition that led to it and the next choice to
+/// follow from it.
+struct Frame {
+    id: usize,
+    config: Configuration,
+    transition: Option<Transition>,
+    next: usize,
+}
+
+impl Machine {
+    /// Explores the computation tree from the current configuration,
+    /// following every transition that applies, until a branch reaches an
+    /// accepting state, reporting the nodes of the tree to `observer`. On
+    /// acceptance the machine is left in the accepting configuration.
+    pub fn search(
+        &mut self,
+        strategy: Strategy,
+        limits: Limits,
+        observer: &mut impl Observer,
+    ) -> Result<Search, RuntimeError> {
+        let root = self.config.clone();
+        let mut stats = Statistics::default();
+        let outcome = match strategy {
+            Strategy::BreadthFirst => {
+                self.breadth_first(&root, limits, false, &mut stats, observer)?
+            }
+            Strategy::Memoized => self.breadth_first(&root, limits, true, &mut stats, observer)?,
+            Strategy::DepthFirst => {
+                let budget = limits.configurations;
+                self.depth_first(&root, limits.depth, budget, &mut stats, observer)?
+            }
+            Strategy::IterativeDeepening => {
+                self.iterative_deepening(&root, limits, &mut stats, observer)?
+            }
+        };
+        let (verdict, path) = match outcome {
+            Outcome::Accept(path) => {
+                let last = &path[path.len() - 1];
+                self.config = last.configuration.clone();
+                self.steps = last.number;
+                (Verdict::Accept, path)
+            }
+            Outcome::Reject => (Verdict::Reject, Vec::new()),
+            Outcome::Exhausted => (Verdict::NeverAccepts, Vec::new()),
+            Outcome::Cut => (Verdict::SearchLimit, Vec::new()),
+        };
+        Ok(Search {
+            verdict,
+            path,
+            statistics: stats,
+        })
+    }
+
+    /// Only the frontier keeps whole configurations, the other nodes keep
+    /// their parent and the transition from it, and the accepting branch
+    /// is replayed from the root.
+    fn breadth_first(
+        &self,
+        root: &Configuration,
+        limits: Limits,
+        memoize: bool,
+        statistics: &mut Statistics,
+        observer: &mut impl Observer,
+    ) -> Result<Outcome, RuntimeError> {
+        statistics.iterations += 1;
+        statistics.explored += 1;
+        statistics.peak = statistics.peak.max(1);
+        observer.node(self, 0, None, root);
+        if self.accepting.contains(&root.state) {
+            observer.leaf(self, 0, Leaf::Accepting);
+            return Ok(Outcome::Accept(replay(root, Vec::new())?));
+        }
+        // The parent and the transition of node `i + 1`, the root is node 0.
+        let mut links: Vec<(usize, Transition)> = Vec::new();
+        // The node where each configuration was first reached.
+        let mut visited = HashMap::new();
+        if memoize {
+            visited.insert(root.clone(), 0);
+        }
+        let mut queue = VecDeque::from([(0, root.clone(), 0)]);
+        let mut cut = false;
+        let mut pruned = false;
+        while let Some((node, config, depth)) = queue.pop_front() {
+            let choices = config.choices(&self.transitions);
+            if choices.is_empty() {
+                observer.leaf(self, node, Leaf::Rejecting);
+                continue;
+            }
+            if limits.depth == Some(depth) {
+                observer.leaf(self, node, Leaf::CutOff);
+                cut = true;
+                continue;
+            }
+            for transition in choices {
+                if limits
+                    .configurations
+                    .is_some_and(|max| statistics.explored >= max)
+                {
+                    return Ok(Outcome::Cut);
+                }
+                let mut next = config.clone();
+                next.apply(transition)?;
+                statistics.explored += 1;
+                statistics.depth = statistics.depth.max(depth + 1);
+                links.push((node, transition.clone()));
+                let id = links.len();
+                observer.node(self, id, Some((node, transition)), &next);
+                if memoize && let Some(&first) = visited.get(&next) {
+                    observer.leaf(self, id, Leaf::Duplicate);
+                    statistics.duplicates += 1;
+                    // Only a configuration met again on its own branch runs
+                    // forever, others are branches that merge.
+                    pruned |= ancestors(&links, node).any(|a| a == first);
+                    continue;
+                }
+                if memoize {
+                    visited.insert(next.clone(), id);
+                }
+                if self.accepting.contains(&next.state) {
+                    observer.leaf(self, id, Leaf::Accepting);
+                    let mut branch = Vec::new();
+                    let mut node = id;
+                    while node > 0 {
+                        let (parent, transition) = &links[node - 1];
+                        branch.push(transition.clone());
+                        node = *parent;
+                    }
+                    branch.reverse();
+                    return Ok(Outcome::Accept(replay(root, branch)?));
+                }
+                queue.push_back((id, next, depth + 1));
+                statistics.peak = statistics.peak.max(queue.len() + visited.len());
+            }
+        }
+        Ok(if cut {
+            Outcome::Cut
+        } else if pruned {
+            Outcome::Exhausted
+        } else {
+            Outcome::Reject
+        })
+    }
+
+    fn depth_first(
+        &self,
+        root: &Configuration,
+        bound: Option<u64>,
+        budget: Option<usize>,
+        statistics: &mut Statistics,
+        observer: &mut impl Observer,
+    ) -> Result<Outcome, RuntimeError> {
+        statistics.iterations += 1;
+        statistics.explored += 1;
+        statistics.peak = statistics.peak.max(1);
+        observer.node(self, 0, None, root);
+        if self.accepting.contains(&root.state) {
+            observer.leaf(self, 0, Leaf::Accepting);
+            return Ok(Outcome::Accept(replay(root, Vec::new())?));
+        }
+        let mut ids = 0;
+        let mut stack = vec![Frame {
+            id: 0,
+            config: root.clone(),
+            transition: None,
+            next: 0,
+        }];
+        let mut cut = false;
+        while let Some(top) = stack.len().checked_sub(1) {
+            let (frame, depth) = (&mut stack[top], top as u64);
+            let choices = frame.config.choices(&self.transitions);
+            if frame.next == choices.len() || bound == Some(depth) {
+                if choices.is_empty() {
+                    observer.leaf(self, frame.id, Leaf::Rejecting);
+                } else if frame.next == 0 {
+                    observer.leaf(self, frame.id, Leaf::CutOff);
+                    cut = true;
+                }
+                stack.pop();
+                continue;
+            }
+            if budget.is_some_and(|max| statistics.explored >= max) {
+                return Ok(Outcome::Cut);
+            }
+            let transition = &choices[frame.next];
+            frame.next += 1;
+            let mut config = frame.config.clone();
+            config.apply(transition)?;
+            statistics.explored += 1;
+            statistics.depth = statistics.depth.max(depth + 1);
+            ids += 1;
+            observer.node(self, ids, Some((frame.id, transition)), &config);
+            let accepted = self.accepting.contains(&config.state);
+            if accepted {
+                observer.leaf(self, ids, Leaf::Accepting);
+            }
+            stack.push(Frame {
+                id: ids,
+                config,
+                transition: Some(transition.clone()),
+                next: 0,
+            });
+            statistics.peak = statistics.peak.max(stack.len());
+            if accepted {
+                let steps = stack.into_iter().enumerate().map(|(i, frame)| Step {
+                    number: i as u64,
+                    transition: frame.transition,
+                    configuration: frame.config,
+                });
+                return Ok(Outcome::Accept(steps.collect()));
+            }
+        }
+        Ok(if cut { Outcome::Cut } else { Outcome::Reject })
+    }
+
+    fn iterative_deepening(
+        &self,
+        root: &Configuration,
+        limits: Limits,
+        statistics: &mut Statistics,
+        observer: &mut impl Observer,
+    ) -> Result<Outcome, RuntimeError> {
+        let mut bound = 0;
+        loop {
+            let budget = limits.configurations;
+            let outcome = self.depth_first(root, Some(bound), budget, statistics, observer)?;
+            let exhausted = limits
+                .configurations
+                .is_some_and(|max| statistics.explored >= max);
+            if !matches!(outcome, Outcome::Cut) || exhausted || limits.depth == Some(bound) {
+                return Ok(outcome);
+            }
+            bound += 1;
+        }
+    }
+}
+
+/// Node `node` of a breadth-first search and the nodes above it, up to the
+/// root.
+fn ancestors(links: &[(usize, Transition)], node: usize) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(Some(node), |&n| (n > 0).then(|| links[n - 1].0))
+}
+
+/// Follows `branch` from `root`.
+fn replay(root: &Configuration, branch: Vec<Transition>) -> Result<Vec<Step>, RuntimeError> {
+    let mut config = root.clone();
+    let mut path = vec![Step {
+        number: 0,
+        transition: None,
+        configuration: config.clone(),
+    }];
+    for (i, transition) in branch.into_iter().enumerate() {
+        config.apply(&transition)?;
+        path.push(Step {
+            number: i as u64 + 1,
+            transition: Some(transition),
+            configuration: config.clone(),
+        });
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::tests::machine;
+
+    /// Accepts the words with an `a` right after some `b`, guessing which
+    /// `b` it is.
+    const GUESS: &str = "version: 2
+alphabet: a b
+blank: _
+start: q0
+accept: acc
+reject: rej
+nondeterministic:
+q0 a q0 a R
+q0 b q0 b R
+q0 b q1 b R
+q1 a acc a N
+q0 _ rej _ N
+";
+
+    /// Moves between two states forever without moving the head.
+    const SPIN: &str = "version: 2
+alphabet: a
+blank: _
+start: q0
+accept: acc
+nondeterministic:
+q0 a q0 a N
+q0 a q1 a N
+q1 a q0 a N
+";
+
+    const STRATEGIES: [Strategy; 4] = [
+        Strategy::BreadthFirst,
+        Strategy::DepthFirst,
+        Strategy::IterativeDeepening,
+        Strategy::Memoized,
+    ];
+
+    fn search(source: &str, input: &str, strategy: Strategy, limits: Limits) -> Search {
+        let mut machine = machine(source, input);
+        machine.search(strategy, limits, &mut ()).unwrap()
+    }
+
+    #[test]
+    fn every_strategy_accepts() {
+        for strategy in STRATEGIES {
+            let search = search(GUESS, "aabab", strategy, Limits::default());
+            assert_eq!(search.verdict, Verdict::Accept, "{strategy}");
+            assert_eq!(search.path.len(), 5, "{strategy}");
+        }
+    }
+
+    #[test]
+    fn every_strategy_rejects() {
+        for strategy in STRATEGIES {
+            let search = search(GUESS, "bbb", strategy, Limits::default());
+            assert_eq!(search.verdict, Verdict::Reject, "{strategy}");
+            assert!(search.path.is_empty(), "{strategy}");
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let limits = Limits {
+            depth: Some(5),
+            configurations: None,
+        };
+        for strategy in STRATEGIES.into_iter().take(3) {
+            let search = search(SPIN, "a", strategy, limits);
+            assert_eq!(search.verdict, Verdict::SearchLimit, "{strategy}");
+            assert_eq!(search.statistics.depth, 5, "{strategy}");
+        }
+    }
+
+    /// Guesses between two states that both lead to the same rejecting
+    /// configuration.
+    const DIAMOND: &str = "version: 2
+alphabet: a
+blank: _
+start: q0
+accept: acc
+reject: rej
+nondeterministic:
+q0 a q1 a N
+q0 a q2 a N
+q1 a q3 a N
+q2 a q3 a N
+q3 a rej a N
+";
+
+    #[test]
+    fn memoized_diamond_rejects() {
+        for strategy in [Strategy::BreadthFirst, Strategy::Memoized] {
+            let search = search(DIAMOND, "a", strategy, Limits::default());
+            assert_eq!(search.verdict, Verdict::Reject, "{strategy}");
+        }
+        let search = search(DIAMOND, "a", Strategy::Memoized, Limits::default());
+        assert_eq!(search.statistics.duplicates, 1);
+    }
+
+    #[test]
+    fn memoized_loop_never_accepts() {
+        let search = search(SPIN, "a", Strategy::Memoized, Limits::default());
+        assert_eq!(search.verdict, Verdict::NeverAccepts);
+        assert_eq!(search.statistics.duplicates, 2);
+    }
+}
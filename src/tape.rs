@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+};
+
+use crate::{Direction, Symbol, error::RuntimeError};
+
+pub type Cell = i64;
+
+#[derive(Clone, Debug)]
+pub struct Tape {
+    cells: VecDeque<Symbol>,
+    offset: Cell,
+    head: Cell,
+    leftmost: Cell,
+    rightmost: Cell,
+    blank: Symbol,
+}
+
+impl Tape {
+    pub fn new(blank: Symbol) -> Self {
+        Self {
+            cells: VecDeque::new(),
+            offset: 0,
+            head: 0,
+            leftmost: 0,
+            rightmost: 0,
+            blank,
+        }
+    }
+
+    pub(crate) fn push(&mut self, symbol: Symbol) {
+        self.cells.push_back(symbol);
+    }
+
+    pub fn head(&self) -> Cell {
+        self.head
+    }
+
+    pub fn leftmost(&self) -> Cell {
+        self.leftmost
+    }
+
+    pub fn rightmost(&self) -> Cell {
+        self.rightmost
+    }
+
+    pub fn span(&self) -> RangeInclusive<Cell> {
+        let end = self.offset + self.cells.len() as Cell - 1;
+        self.leftmost().min(self.offset)..=self.rightmost().max(end)
+    }
+
+    pub fn get(&self, cell: Cell) -> Symbol {
+        usize::try_from(cell - self.offset)
+            .ok()
+            .and_then(|i| self.cells.get(i))
+            .copied()
+            .unwrap_or(self.blank)
+    }
+
+    pub fn read(&self) -> Symbol {
+        self.get(self.head)
+    }
+
+    /// Writes `symbol` under the head and returns whether the tape had to
+    /// grow to store it.
+    pub(crate) fn write(&mut self, symbol: Symbol) -> bool {
+        let stored = self.cells.len();
+        while self.head < self.offset {
+            self.cells.push_front(self.blank);
+            self.offset -= 1;
+        }
+        let i = (self.head - self.offset) as usize;
+        if i >= self.cells.len() {
+            self.cells.resize(i + 1, self.blank);
+        }
+        self.cells[i] = symbol;
+        self.cells.len() > stored
+    }
+
+    pub(crate) fn shift(&mut self, dir: Direction) -> Result<(), RuntimeError> {
+        self.head = match dir {
+            Direction::Right => self.head.checked_add(1),
+            Direction::Left => self.head.checked_sub(1),
+            Direction::None => Some(self.head),
+        }
+        .ok_or(RuntimeError::EndOfTape)?;
+        self.leftmost = self.leftmost.min(self.head);
+        self.rightmost = self.rightmost.max(self.head);
+        Ok(())
+    }
+
+    /// The first non-blank cell and the symbols up to the last non-blank
+    /// one, so that tapes differing only in explored blanks compare equal.
+    fn contents(&self) -> (Cell, impl Iterator<Item = Symbol> + '_) {
+        let first = self.cells.iter().position(|&s| s != self.blank);
+        let last = self.cells.iter().rposition(|&s| s != self.blank);
+        let (start, range) = match first.zip(last) {
+            Some((first, last)) => (self.offset + first as Cell, first..last + 1),
+            None => (0, 0..0),
+        };
+        (start, self.cells.range(range).copied())
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.cells.clear();
+        self.offset = 0;
+        self.head = 0;
+        self.leftmost = 0;
+        self.rightmost = 0;
+    }
+}
+
+impl PartialEq for Tape {
+    fn eq(&self, other: &Self) -> bool {
+        let (start, cells) = self.contents();
+        let (other_start, other_cells) = other.contents();
+        self.head == other.head && start == other_start && cells.eq(other_cells)
+    }
+}
+
+impl Eq for Tape {}
+
+impl Hash for Tape {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (start, cells) = self.contents();
+        self.head.hash(state);
+        start.hash(state);
+        cells.for_each(|s| s.hash(state));
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt::Write;
+
+use crate::{Configuration, Machine, State, Symbol, Transition, observe::Observer, search::Leaf};
+
+/// Records the computation tree explored by a search, see
+/// [`Machine::search`], to export it as Graphviz DOT or JSON. A search that
+/// starts over from the initial configuration replaces the tree, so with
+/// iterative deepening only the last iteration is kept.
+#[derive(Clone, Debug, Default)]
+pub struct Tree {
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    configuration: String,
+    from: (State, Box<[Symbol]>),
+    parent: Option<(usize, String)>,
+    leaf: Option<Leaf>,
+    expanded: bool,
+}
+
+impl Observer for Tree {
+    fn node(
+        &mut self,
+        machine: &Machine,
+        _node: usize,
+        parent: Option<(usize, &Transition)>,
+        config: &Configuration,
+    ) {
+        if parent.is_none() {
+            self.nodes.clear();
+        }
+        let parent = parent.map(|(i, transition)| {
+            let parent = &mut self.nodes[i];
+            parent.expanded = true;
+            let from = (parent.from.0, &*parent.from.1);
+            (i, machine.render_transition(from, transition))
+        });
+        self.nodes.push(Node {
+            configuration: machine.render(config),
+            from: (config.state, config.reads()),
+            parent,
+            leaf: None,
+            expanded: false,
+        });
+    }
+
+    fn leaf(&mut self, _machine: &Machine, node: usize, leaf: Leaf) {
+        self.nodes[node].leaf = Some(leaf);
+    }
+}
+
+impl Tree {
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The kind of leaf node `i` is, or `None` if the search followed it.
+    /// Nodes the search reached but never got to, because it accepted or
+    /// ran out of configurations first, count as cut off.
+    pub fn leaf(&self, i: usize) -> Option<Leaf> {
+        let node = &self.nodes[i];
+        node.leaf.or((!node.expanded).then_some(Leaf::CutOff))
+    }
+
+    /// The tree as a Graphviz digraph, with a box per configuration in
+    /// describe notation and edges labelled with the transition lines.
+    /// Accepting leaves are green, rejecting leaves red, cut-off leaves
+    /// dashed and duplicates dotted.
+    pub fn dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    node [shape=box, fontname=monospace];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let style = match self.leaf(i) {
+                None => "",
+                Some(Leaf::Accepting) => ", style=filled, fillcolor=palegreen",
+                Some(Leaf::Rejecting) => ", style=filled, fillcolor=lightpink",
+                Some(Leaf::CutOff) => ", style=dashed",
+                Some(Leaf::Duplicate) => ", style=dotted",
+            };
+            let label = dot_label(&node.configuration);
+            _ = writeln!(dot, "    {i} [label=\"{label}\"{style}];");
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some((parent, transition)) = &node.parent {
+                let label = dot_label(transition);
+                _ = writeln!(dot, "    {parent} -> {i} [label=\"{label}\"];");
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The tree as a JSON object with a `nodes` array, where every node has
+    /// an `id`, its `configuration` in describe notation and the kind of
+    /// `leaf` it is (`"accepting"`, `"rejecting"`, `"cut-off"`,
+    /// `"duplicate"` or `null`), and an `edges` array, where every edge has
+    /// the ids of the nodes it goes `from` and `to` and its `transition`.
+    pub fn json(&self) -> String {
+        let nodes = self.nodes.iter().enumerate().map(|(i, node)| {
+            let leaf = match self.leaf(i) {
+                Some(leaf) => json_string(&leaf.to_string()),
+                None => "null".to_owned(),
+            };
+            format!(
+                "    {{\"id\": {i}, \"configuration\": {}, \"leaf\": {leaf}}}",
+                json_string(&node.configuration)
+            )
+        });
+        let edges = self.nodes.iter().enumerate().filter_map(|(i, node)| {
+            let (parent, transition) = node.parent.as_ref()?;
+            Some(format!(
+                "    {{\"from\": {parent}, \"to\": {i}, \"transition\": {}}}",
+                json_string(transition)
+            ))
+        });
+        format!(
+            "{{\n  \"nodes\": {},\n  \"edges\": {}\n}}",
+            json_array(nodes.collect()),
+            json_array(edges.collect())
+        )
+    }
+}
+
+fn json_array(items: Vec<String>) -> String {
+    if items.is_empty() {
+        return "[]".to_owned();
+    }
+    format!("[\n{}\n  ]", items.join(",\n"))
+}
+
+/// Escapes a label for a DOT string, with the lines of multi-tape
+/// configurations left-aligned.
+fn dot_label(text: &str) -> String {
+    let mut label = String::new();
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                label.push('\\');
+                label.push(c);
+            }
+            '\n' => label.push_str("\\l"),
+            c => label.push(c),
+        }
+    }
+    if text.contains('\n') {
+        label.push_str("\\l");
+    }
+    label
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                json.push('\\');
+                json.push(c);
+            }
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            '\r' => json.push_str("\\r"),
+            c if c.is_control() => _ = write!(json, "\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limits, Strategy, Verdict, machine::tests::machine};
+
+    /// Rejects on one branch after two steps and accepts on the other
+    /// after three.
+    const BRANCHES: &str = "version: 2
+alphabet: a
+blank: _
+start: q
+accept: acc
+reject: rej
+nondeterministic:
+q a r a N
+q a s a N
+r a rej a N
+s a t a N
+t a acc a N
+";
+
+    fn tree(source: &str, strategy: Strategy, limits: Limits) -> (Verdict, Tree) {
+        let mut tree = Tree::default();
+        let mut machine = machine(source, "a");
+        let search = machine.search(strategy, limits, &mut tree).unwrap();
+        (search.verdict, tree)
+    }
+
+    fn leaves(tree: &Tree) -> Vec<Option<Leaf>> {
+        (0..tree.len()).map(|i| tree.leaf(i)).collect()
+    }
+
+    #[test]
+    fn accepting_and_rejecting_leaves() {
+        let (verdict, tree) = tree(BRANCHES, Strategy::BreadthFirst, Limits::default());
+        assert_eq!(verdict, Verdict::Accept);
+        assert_eq!(
+            leaves(&tree),
+            [
+                None,
+                None,
+                None,
+                Some(Leaf::Rejecting),
+                None,
+                Some(Leaf::Accepting)
+            ]
+        );
+    }
+
+    #[test]
+    fn unexpanded_nodes_are_cut_off() {
+        let source = "version: 2
+alphabet: a
+blank: _
+start: q
+accept: acc
+nondeterministic:
+q a q a R
+q a acc a N
+";
+        let (_, tree) = tree(source, Strategy::BreadthFirst, Limits::default());
+        assert_eq!(
+            leaves(&tree),
+            [None, Some(Leaf::CutOff), Some(Leaf::Accepting)]
+        );
+    }
+
+    #[test]
+    fn depth_limit_cuts_off() {
+        let limits = Limits {
+            depth: Some(1),
+            configurations: None,
+        };
+        let (verdict, tree) = tree(BRANCHES, Strategy::BreadthFirst, limits);
+        assert_eq!(verdict, Verdict::SearchLimit);
+        assert_eq!(
+            leaves(&tree),
+            [None, Some(Leaf::CutOff), Some(Leaf::CutOff)]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let source = BRANCHES.replace("s a t a N", "s a rej a N");
+        let (verdict, tree) = tree(&source, Strategy::Memoized, Limits::default());
+        assert_eq!(verdict, Verdict::Reject);
+        assert_eq!(
+            leaves(&tree),
+            [
+                None,
+                None,
+                None,
+                Some(Leaf::Rejecting),
+                Some(Leaf::Duplicate)
+            ]
+        );
+        assert_eq!(
+            tree.dot(),
+            "digraph {
+    node [shape=box, fontname=monospace];
+    0 [label=\"(q)a\"];
+    1 [label=\"(r)a\"];
+    2 [label=\"(s)a\"];
+    3 [label=\"(rej)a\", style=filled, fillcolor=lightpink];
+    4 [label=\"(rej)a\", style=dotted];
+    0 -> 1 [label=\"q a r a N\"];
+    0 -> 2 [label=\"q a s a N\"];
+    1 -> 3 [label=\"r a rej a N\"];
+    2 -> 4 [label=\"s a rej a N\"];
+}
+"
+        );
+        let json = tree.json();
+        assert!(
+            json.contains("{\"id\": 4, \"configuration\": \"(rej)a\", \"leaf\": \"duplicate\"}")
+        );
+        assert!(json.contains("{\"from\": 2, \"to\": 4, \"transition\": \"s a rej a N\"}"));
+    }
+
+    #[test]
+    fn iterative_deepening_keeps_the_last_iteration() {
+        let mut tree = Tree::default();
+        let mut machine = machine(BRANCHES, "a");
+        let search = machine
+            .search(Strategy::IterativeDeepening, Limits::default(), &mut tree)
+            .unwrap();
+        assert!(search.statistics.iterations > 1);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.nodes[0].configuration, "(q)a");
+        assert!(tree.nodes[0].parent.is_none());
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(dot_label("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(dot_label("1: (q)a\n2: (q)_"), "1: (q)a\\l2: (q)_\\l");
+        assert_eq!(
+            json_string("\"\\\n\t\r\u{1}é"),
+            "\"\\\"\\\\\\n\\t\\r\\u0001é\""
+        );
+    }
+}